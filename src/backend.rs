@@ -1,3 +1,5 @@
+pub mod command_tree;
 pub mod datapack;
 pub mod error;
 pub mod generator;
+pub mod optimizer;
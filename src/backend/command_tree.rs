@@ -0,0 +1,252 @@
+//! Checks commands against the Brigadier command tree in a `commands.json`
+//! report of the Minecraft data generator
+//! (`java -DbundlerMainClass=net.minecraft.data.Main -jar server.jar --reports`).
+
+use serde_json::{Map, Value};
+
+enum NodeKind {
+    Root,
+    Literal(String),
+    Argument {
+        parser: String,
+        properties: Map<String, Value>,
+    },
+}
+
+struct Node {
+    kind: NodeKind,
+    executable: bool,
+    children: Vec<usize>,
+    /// The path of the node parsing continues at, e.g. `["execute"]` for
+    /// `execute as <targets>`. Empty for the root.
+    redirect: Option<Vec<String>>,
+}
+
+pub struct CommandTree {
+    /// The root is `nodes[0]`.
+    nodes: Vec<Node>,
+}
+
+const ROOT: usize = 0;
+
+impl CommandTree {
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let root: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+        let mut tree = Self { nodes: vec![] };
+        tree.add_node(&root, NodeKind::Root)?;
+        Ok(tree)
+    }
+
+    fn add_node(&mut self, json: &Value, kind: NodeKind) -> Result<usize, String> {
+        let index = self.nodes.len();
+        let redirect = match json.get("redirect") {
+            Some(Value::Array(path)) => Some(
+                path.iter()
+                    .map(|name| name.as_str().map(str::to_owned))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or("invalid redirect")?,
+            ),
+            _ => None,
+        };
+        let executable = json.get("executable").and_then(Value::as_bool) == Some(true);
+        // `execute ... run` redirects to the root, whose path is empty and
+        // left out of the report
+        let redirect = match redirect {
+            None if !executable && json.get("children").is_none() && index != ROOT => Some(vec![]),
+            redirect => redirect,
+        };
+        self.nodes.push(Node {
+            kind,
+            executable,
+            children: vec![],
+            redirect,
+        });
+        if let Some(Value::Object(children)) = json.get("children") {
+            for (name, child) in children {
+                let kind = match child.get("type").and_then(Value::as_str) {
+                    Some("literal") => NodeKind::Literal(name.to_owned()),
+                    Some("argument") => NodeKind::Argument {
+                        parser: child
+                            .get("parser")
+                            .and_then(Value::as_str)
+                            .ok_or("argument without parser")?
+                            .to_owned(),
+                        properties: match child.get("properties") {
+                            Some(Value::Object(properties)) => properties.clone(),
+                            _ => Map::new(),
+                        },
+                    },
+                    _ => return Err(format!("unknown node type of \"{}\"", name)),
+                };
+                let child = self.add_node(child, kind)?;
+                self.nodes[index].children.push(child);
+            }
+        }
+        Ok(index)
+    }
+
+    /// Checks `command`, a line of an mcfunction file. In macro lines, a
+    /// token with `$(...)` in it may stand for any argument.
+    pub fn validate(&self, command: &str) -> Result<(), String> {
+        let command = command.strip_prefix('$').unwrap_or(command);
+        let mut furthest = 0;
+        if self.match_node(ROOT, command, 0, &mut furthest) {
+            return Ok(());
+        }
+        Err(format!(
+            "unknown or incomplete command: \"{}<--[HERE]\"",
+            &command[..furthest]
+        ))
+    }
+
+    /// Whether the rest of `command` after `pos`, where `node` ended, can be
+    /// parsed. `furthest` is where parsing got to.
+    fn match_node(&self, node: usize, command: &str, pos: usize, furthest: &mut usize) -> bool {
+        *furthest = (*furthest).max(pos);
+        if pos == command.len() {
+            return self.nodes[node].executable;
+        }
+        let start = if node == ROOT {
+            pos
+        } else if command[pos..].starts_with(' ') {
+            pos + 1
+        } else {
+            return false;
+        };
+        let Some(parent) = self.redirect_target(node) else {
+            return false;
+        };
+        self.nodes[parent]
+            .children
+            .iter()
+            .any(|&child| match self.read(child, &command[start..]) {
+                Some(len) => self.match_node(child, command, start + len, furthest),
+                None => false,
+            })
+    }
+
+    fn redirect_target(&self, node: usize) -> Option<usize> {
+        let Some(path) = &self.nodes[node].redirect else {
+            return Some(node);
+        };
+        let mut target = ROOT;
+        for name in path {
+            target = *self.nodes[target].children.iter().find(|&&child| {
+                matches!(&self.nodes[child].kind, NodeKind::Literal(literal) if literal == name)
+            })?;
+        }
+        Some(target)
+    }
+
+    /// How long the part of `input` parsed by `node` is.
+    fn read(&self, node: usize, input: &str) -> Option<usize> {
+        match &self.nodes[node].kind {
+            NodeKind::Root => None,
+            NodeKind::Literal(literal) => {
+                let len = input.find(' ').unwrap_or(input.len());
+                (&input[..len] == literal || input[..len].contains("$(")).then_some(len)
+            }
+            NodeKind::Argument { parser, properties } => read_argument(parser, properties, input),
+        }
+    }
+}
+
+/// Reads a token, which ends at a space outside of brackets and quotes.
+fn read_token(input: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in input.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' | '(' => depth += 1,
+                ']' | '}' | ')' if depth > 0 => depth -= 1,
+                ']' | '}' | ')' => return None,
+                ' ' if depth == 0 => return (i > 0).then_some(i),
+                _ => {}
+            },
+        }
+    }
+    (depth == 0 && quote.is_none() && !input.is_empty()).then_some(input.len())
+}
+
+/// Reads `count` tokens separated by spaces, each of which must satisfy
+/// `valid` unless it is a macro argument.
+fn read_tokens(input: &str, count: usize, valid: impl Fn(&str) -> bool) -> Option<usize> {
+    let mut len = 0;
+    for i in 0..count {
+        if i > 0 {
+            input[len..].starts_with(' ').then_some(())?;
+            len += 1;
+        }
+        let token_len = read_token(&input[len..])?;
+        let token = &input[len..len + token_len];
+        if !token.contains("$(") && !valid(token) {
+            return None;
+        }
+        len += token_len;
+    }
+    Some(len)
+}
+
+fn is_number(token: &str, integer: bool) -> bool {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    let (int_part, frac_part) = match digits.split_once('.') {
+        Some(_) if integer => return false,
+        Some((int_part, frac_part)) => (int_part, frac_part),
+        None => (digits, ""),
+    };
+    !(int_part.is_empty() && frac_part.is_empty())
+        && int_part.chars().all(|c| c.is_ascii_digit())
+        && frac_part.chars().all(|c| c.is_ascii_digit())
+}
+
+fn is_coordinate(token: &str) -> bool {
+    let number = token
+        .strip_prefix('~')
+        .or_else(|| token.strip_prefix('^'))
+        .unwrap_or(token);
+    (number.is_empty() && token != number) || is_number(number, false)
+}
+
+/// Checks a number against the `min` and `max` properties of its parser.
+fn in_bounds(token: &str, properties: &Map<String, Value>) -> bool {
+    let Ok(value) = token.parse::<f64>() else {
+        return false;
+    };
+    let min = properties.get("min").and_then(Value::as_f64);
+    let max = properties.get("max").and_then(Value::as_f64);
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+fn read_argument(parser: &str, properties: &Map<String, Value>, input: &str) -> Option<usize> {
+    match parser {
+        "brigadier:bool" => read_tokens(input, 1, |token| token == "true" || token == "false"),
+        "brigadier:integer" | "brigadier:long" => read_tokens(input, 1, |token| {
+            is_number(token, true) && in_bounds(token, properties)
+        }),
+        "brigadier:float" | "brigadier:double" => read_tokens(input, 1, |token| {
+            is_number(token, false) && in_bounds(token, properties)
+        }),
+        "brigadier:string" => match properties.get("type").and_then(Value::as_str) {
+            Some("greedy") => (!input.is_empty()).then_some(input.len()),
+            Some("word") => read_tokens(input, 1, |token| {
+                token
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+            }),
+            _ => read_tokens(input, 1, |_| true),
+        },
+        "minecraft:message" => (!input.is_empty()).then_some(input.len()),
+        "minecraft:block_pos" | "minecraft:vec3" => read_tokens(input, 3, is_coordinate),
+        "minecraft:column_pos" | "minecraft:vec2" | "minecraft:rotation" => {
+            read_tokens(input, 2, is_coordinate)
+        }
+        _ => read_tokens(input, 1, |_| true),
+    }
+}
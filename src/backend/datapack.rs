@@ -1,26 +1,48 @@
 use std::{
-    fs::{create_dir, remove_dir_all, File},
+    collections::{BTreeMap, HashSet},
+    fs::{create_dir, create_dir_all, remove_dir_all, File},
     io::{Result, Write},
+    path::Path,
 };
 
 pub mod mcscript_datapack;
 
+/// The Minecraft version targeted unless another one is set, the same as
+/// the default of `--mc-version`.
+pub const DEFAULT_MC_VERSION: (u32, u32, u32) = (1, 21, 0);
+
 #[derive(Clone)]
 pub struct Datapack {
     name: String,
     namespaces: Vec<Namespace>,
+    /// Function tags such as `minecraft:tick`, mapped to the functions they
+    /// list.
+    function_tags: BTreeMap<String, Vec<String>>,
+    /// The targeted Minecraft version, which decides the `pack_format` and
+    /// the names of the directories.
+    mc_version: (u32, u32, u32),
 }
 
 #[derive(Clone)]
 pub struct Namespace {
     name: String,
     mcfunctions: Vec<Mcfunction>,
+    resources: Vec<Resource>,
+}
+
+/// A JSON file of a namespace other than a function, e.g. an advancement.
+#[derive(Clone)]
+pub struct Resource {
+    /// The directory of the resource, e.g. `advancement` or `tags/block`.
+    kind: String,
+    name: String,
+    json: String,
 }
 
 #[derive(Clone)]
 pub struct Mcfunction {
     name: String,
-    content: String,
+    commands: Vec<String>,
 }
 
 impl Datapack {
@@ -28,9 +50,15 @@ impl Datapack {
         Self {
             name,
             namespaces: vec![],
+            function_tags: BTreeMap::new(),
+            mc_version: DEFAULT_MC_VERSION,
         }
     }
 
+    pub fn set_mc_version(&mut self, version: (u32, u32, u32)) {
+        self.mc_version = version;
+    }
+
     pub fn write_to_file(&self) -> Result<()> {
         match create_dir(&self.name) {
             Ok(()) => {}
@@ -45,10 +73,29 @@ impl Datapack {
         let pack_mcmeta = [&self.name, "pack.mcmeta"].join("/");
         create_dir([&self.name, "data"].join("/"))?;
         let mut pack_mcmeta = File::create(pack_mcmeta)?;
-        pack_mcmeta.write_all("{\n    \"pack\": {\n        \"description\": \"Generated from mcscript.\",\n        \"pack_format\": 48\n    }\n}".as_bytes())?;
+        pack_mcmeta.write_all(format!("{{\n    \"pack\": {{\n        \"description\": \"Generated from mcscript.\",\n        \"pack_format\": {}\n    }}\n}}", pack_format(self.mc_version)).as_bytes())?;
 
         for namespace in &self.namespaces {
-            namespace.write_to_file(&self.name)?;
+            namespace.write_to_file(&self.name, self.mc_version)?;
+        }
+
+        for (tag, functions) in &self.function_tags {
+            let (tag_namespace, tag_name) = tag.split_once(':').unwrap();
+            let dir = [
+                &self.name,
+                "data",
+                tag_namespace,
+                &directory("tags/function", self.mc_version),
+            ]
+            .join("/");
+            create_dir_all(&dir)?;
+            let mut file = File::create(format!("{}/{}.json", dir, tag_name))?;
+            let values = functions
+                .iter()
+                .map(|function| format!("        \"{}\"", function))
+                .collect::<Vec<_>>()
+                .join(",\n");
+            file.write_all(format!("{{\n    \"values\": [\n{}\n    ]\n}}", values).as_bytes())?;
         }
 
         Ok(())
@@ -58,9 +105,73 @@ impl Datapack {
         self.namespaces.push(namespace);
     }
 
+    pub fn append_to_function_tag(&mut self, tag: &str, function: String) {
+        self.function_tags
+            .entry(tag.to_owned())
+            .or_default()
+            .push(function);
+    }
+
+    /// Removes the functions that can't be reached from `roots` or a function
+    /// tag by following the `ns:name` references in commands, and returns
+    /// their `ns:name` ids.
+    pub fn retain_reachable(&mut self, roots: &[String]) -> Vec<String> {
+        let mut reachable = HashSet::new();
+        let mut worklist = roots.to_vec();
+        worklist.extend(self.function_tags.values().flatten().cloned());
+        while let Some(id) = worklist.pop() {
+            if !reachable.insert(id.clone()) {
+                continue;
+            }
+            let Some((namespace_name, mcfunction_name)) = id.split_once(':') else {
+                continue;
+            };
+            let mcfunction = self
+                .namespaces
+                .iter()
+                .filter(|namespace| namespace.name == namespace_name)
+                .flat_map(|namespace| &namespace.mcfunctions)
+                .find(|mcfunction| mcfunction.name == mcfunction_name);
+            if let Some(mcfunction) = mcfunction {
+                for command in &mcfunction.commands {
+                    worklist.extend(referenced_functions(command).map(|id| id.to_owned()));
+                }
+            }
+        }
+        let mut removed = vec![];
+        for namespace in &mut self.namespaces {
+            let namespace_name = namespace.name.clone();
+            namespace.mcfunctions.retain(|mcfunction| {
+                let id = format!("{}:{}", namespace_name, mcfunction.name);
+                let is_reachable = reachable.contains(&id);
+                if !is_reachable {
+                    removed.push(id);
+                }
+                is_reachable
+            });
+        }
+        removed
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn namespaces(&self) -> &[Namespace] {
+        &self.namespaces
+    }
+
+    pub fn namespaces_mut(&mut self) -> &mut [Namespace] {
+        &mut self.namespaces
+    }
+
+    pub fn command_count(&self) -> usize {
+        self.namespaces
+            .iter()
+            .flat_map(|namespace| &namespace.mcfunctions)
+            .map(|mcfunction| mcfunction.command_count())
+            .sum()
+    }
 }
 
 impl Namespace {
@@ -68,18 +179,40 @@ impl Namespace {
         Self {
             name,
             mcfunctions: vec![],
+            resources: vec![],
         }
     }
 
-    pub fn write_to_file(&self, pack_name: &str) -> Result<()> {
+    pub fn write_to_file(&self, pack_name: &str, mc_version: (u32, u32, u32)) -> Result<()> {
+        let function_dir = directory("function", mc_version);
         create_dir([pack_name, "data", &self.name].join("/"))?;
-        create_dir([pack_name, "data", &self.name, "function"].join("/"))?;
+        create_dir([pack_name, "data", &self.name, &function_dir].join("/"))?;
         for mcfunction in &self.mcfunctions {
             let mut file_name = mcfunction.name.clone();
             file_name.push_str(".mcfunction");
-            let mut file =
-                File::create([pack_name, "data", &self.name, "function", &file_name].join("/"))?;
-            file.write_all(mcfunction.content.as_bytes())?;
+            let path = [pack_name, "data", &self.name, &function_dir, &file_name].join("/");
+            // e.g. the wrappers `api/f` of `#[export]` functions
+            if let Some(dir) = Path::new(&path).parent() {
+                create_dir_all(dir)?;
+            }
+            let mut file = File::create(path)?;
+            for command in &mcfunction.commands {
+                file.write_all(command.as_bytes())?;
+                file.write_all(b"\n")?;
+            }
+        }
+        for resource in &self.resources {
+            let path = format!(
+                "{}/data/{}/{}/{}.json",
+                pack_name,
+                self.name,
+                directory(&resource.kind, mc_version),
+                resource.name
+            );
+            if let Some(dir) = Path::new(&path).parent() {
+                create_dir_all(dir)?;
+            }
+            File::create(path)?.write_all(resource.json.as_bytes())?;
         }
         Ok(())
     }
@@ -88,16 +221,50 @@ impl Namespace {
         self.mcfunctions.push(mcfunction);
     }
 
+    pub fn append_resource(&mut self, resource: Resource) {
+        self.resources.push(resource);
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    pub fn mcfunctions(&self) -> &[Mcfunction] {
+        &self.mcfunctions
+    }
+
+    pub fn mcfunctions_mut(&mut self) -> &mut [Mcfunction] {
+        &mut self.mcfunctions
+    }
+}
+
+impl Resource {
+    pub fn new(kind: String, name: String, json: String) -> Self {
+        Self { kind, name, json }
+    }
+
+    pub fn kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn json(&self) -> &str {
+        &self.json
+    }
+
+    pub fn json_mut(&mut self) -> &mut String {
+        &mut self.json
+    }
 }
 
 impl Mcfunction {
     pub fn new(name: String) -> Self {
         Self {
             name,
-            content: "".into(),
+            commands: vec![],
         }
     }
 
@@ -118,12 +285,7 @@ impl Mcfunction {
     }
 
     pub fn append_command(&mut self, command: &str) {
-        let mut command = command.to_owned();
-        if command.contains('$') {
-            command.insert(0, '$');
-        }
-        self.content.push_str(&command);
-        self.content.push('\n');
+        self.commands.push(macro_line(command));
     }
 
     pub fn append_commands(&mut self, commands: Vec<&str>) {
@@ -135,4 +297,81 @@ impl Mcfunction {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// The lines of this function. Macro lines keep their leading `$`, and
+    /// empty lines are kept to separate groups of commands.
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    pub fn commands_mut(&mut self) -> &mut Vec<String> {
+        &mut self.commands
+    }
+
+    pub fn command_count(&self) -> usize {
+        self.commands
+            .iter()
+            .filter(|command| !command.is_empty())
+            .count()
+    }
+}
+
+/// The `pack_format` of data packs for Minecraft `version`, which is 1.20.2
+/// or later. Versions after 1.21.8 get the one of 1.21.8.
+pub fn pack_format(version: (u32, u32, u32)) -> u32 {
+    match version {
+        (1, 20, 2) => 18,
+        (1, 20, 3..=4) => 26,
+        (1, 20, _) => 41,
+        (1, 21, 0..=1) => 48,
+        (1, 21, 2..=3) => 57,
+        (1, 21, 4) => 61,
+        (1, 21, 5) => 71,
+        (1, 21, 6) => 80,
+        _ => 81,
+    }
+}
+
+/// The directory of a namespace for the files of `kind`, e.g. `function` or
+/// `tags/block`. They had plural names before 1.21.
+pub fn directory(kind: &str, version: (u32, u32, u32)) -> String {
+    const RENAMED: [&str; 13] = [
+        "advancement",
+        "function",
+        "item_modifier",
+        "loot_table",
+        "predicate",
+        "recipe",
+        "structure",
+        "tags/block",
+        "tags/entity_type",
+        "tags/fluid",
+        "tags/function",
+        "tags/game_event",
+        "tags/item",
+    ];
+    if version < (1, 21, 0) && RENAMED.contains(&kind) {
+        format!("{}s", kind)
+    } else {
+        kind.to_owned()
+    }
+}
+
+/// Turns `command` into a line of a function, marking it as a macro line if
+/// it contains `$(...)` arguments.
+pub fn macro_line(command: &str) -> String {
+    let mut command = command.to_owned();
+    if command.contains('$') {
+        command.insert(0, '$');
+    }
+    command
+}
+
+/// The tokens of `command` that look like function ids, e.g. `ns:f-label_1`
+/// in `return run function ns:f-label_1 with storage memory:temp`.
+pub fn referenced_functions(command: &str) -> impl Iterator<Item = &str> {
+    command
+        .split(' ')
+        .map(|token| token.trim_matches(|c: char| matches!(c, '"' | '\\' | '{' | '}' | ',')))
+        .filter(|token| token.contains(':'))
 }
@@ -1,16 +1,50 @@
 use super::{Datapack, Mcfunction, Namespace};
 
-pub fn mcscript_datapack(path: String) -> Datapack {
+/// The depth of the deepest tree of calls a loop is driven through, see
+/// `loop`.
+const LOOP_LEVELS: usize = 4;
+
+pub fn mcscript_datapack(path: String, mc_version: (u32, u32, u32)) -> Datapack {
     let mut init = Mcfunction::new("init".into());
     init.append_commands(vec![
         "scoreboard objectives add registers dummy",
+        "function mcscript:reset",
+        "data modify storage memory:temp custom_command_arguments set value {}",
+        "data modify storage memory:temp empty_str set value \"\"",
+        "data modify storage memory:temp custom_command_arguments.empty_str set value \"\"",
+    ]);
+    // Empties the stack, e.g. after a function was cut off by
+    // maxCommandChainLength.
+    let mut reset = Mcfunction::new("reset".into());
+    reset.append_commands(vec![
         "scoreboard players set base_index registers -1",
         "execute store result storage memory:temp base_index int 1.0 run scoreboard players get base_index registers",
         "data modify storage memory:stack frame set value []",
         "data modify storage memory:temp arguments set value {}",
-        "data modify storage memory:temp custom_command_arguments set value {}",
-        "data modify storage memory:temp empty_str set value \"\"",
-        "data modify storage memory:temp custom_command_arguments.empty_str set value \"\"",
+        "scoreboard players set loop_state registers 0",
+        "scoreboard players set abort registers 0",
+        "scoreboard players set running registers 0",
+    ]);
+    // Called before a function is run from outside. `running` counts the
+    // calls from outside that haven't returned yet; if it is positive, the
+    // function is called by MCScript code, e.g. with `run_command!`, and the
+    // stack is in use. Calls cut off by maxCommandChainLength never return,
+    // so `running` only counts the calls of the current tick.
+    let mut guard = Mcfunction::new("guard".into());
+    guard.append_commands(vec![
+        "execute store result score now registers run time query gametime",
+        "execute unless score now registers = running_since registers run scoreboard players set running registers 0",
+        "scoreboard players operation running_since registers = now registers",
+        "execute if score running registers matches 1.. run return 0",
+        // without `--checked`, only loops that ran out of calls set `abort`
+        "scoreboard players set abort registers 0",
+        "execute unless score base_index registers matches -1 run return run function mcscript:repair",
+        "execute if data storage memory:stack frame[0] run return run function mcscript:repair",
+    ]);
+    let mut repair = Mcfunction::new("repair".into());
+    repair.append_commands(vec![
+        "tellraw @a {\"text\": \"MCScript: the stack was not empty, probably because a function was cut off by maxCommandChainLength. It has been reset.\", \"color\": \"yellow\"}",
+        "function mcscript:reset",
     ]);
     let mut pop_frame = Mcfunction::new("pop_frame".into());
     pop_frame.append_commands(vec![
@@ -50,8 +84,65 @@ pub fn mcscript_datapack(path: String) -> Datapack {
     array_erase.append_command(
         "data remove storage $(array_path)[$(subscript)]",
     );
+    // Splits the selector `memory:temp selector` into the `head` before its
+    // closing bracket and the `separator` to put before one more argument.
+    let mut selector_split = Mcfunction::new("selector_split".into());
+    selector_split.append_commands(vec![
+        "data modify storage memory:temp custom_command_arguments.head set from storage memory:temp selector",
+        "data modify storage memory:temp custom_command_arguments.separator set value \"[\"",
+        "data modify storage memory:temp selector_end set string storage memory:temp selector -1",
+        "execute unless data storage memory:temp {selector_end: \"]\"} run return 0",
+        "data modify storage memory:temp custom_command_arguments.head set string storage memory:temp selector 0 -1",
+        "data modify storage memory:temp custom_command_arguments.separator set value \",\"",
+        "data modify storage memory:temp selector_end set string storage memory:temp selector -2",
+        "execute if data storage memory:temp {selector_end: \"[]\"} run data modify storage memory:temp custom_command_arguments.separator set value \"\"",
+    ]);
+    // `loop` calls `$(driver)` until `loop_state` is no longer 0. `loop_n`
+    // calls it 16^n times through a tree of 16 calls per function, and
+    // `loop` runs 16 calls of each depth in turn, so the depth stays small
+    // for short loops and doesn't grow with the number of iterations. A loop
+    // that outlasts all the calls, which is only possible if
+    // maxCommandChainLength was raised, is aborted.
+    let mut loop_root = Mcfunction::new("loop".into());
+    let mut loops = vec![];
+    for level in 0..=LOOP_LEVELS {
+        let call = if level == 0 {
+            "function $(driver)".to_owned()
+        } else {
+            format!("function mcscript:loop_{} {{driver: \"$(driver)\"}}", level)
+        };
+        let mut loop_n = Mcfunction::new(format!("loop_{}", level + 1));
+        for _ in 0..16 {
+            let commands = vec![
+                call.as_str(),
+                "execute unless score loop_state registers matches 0 run return 0",
+            ];
+            loop_root.append_commands(commands.clone());
+            loop_n.append_commands(commands);
+        }
+        if level < LOOP_LEVELS {
+            loops.push(loop_n);
+        }
+    }
+    loop_root.append_command(
+        "function mcscript:abort {message: \"A loop ran for too long and was aborted.\"}",
+    );
+    loops.push(loop_root);
+    let mut schedule = Mcfunction::new("schedule".into());
+    schedule.append_command("schedule function $(function) $(ticks)t");
+    // Stops the loops around, and the generated code returns from every
+    // function until the one that was called from outside.
+    let mut abort = Mcfunction::new("abort".into());
+    abort.append_commands(vec![
+        "tellraw @a {\"text\": \"$(message)\", \"color\": \"red\"}",
+        "scoreboard players set abort registers 1",
+        "scoreboard players set loop_state registers 2",
+    ]);
     let mut namespace = Namespace::new("mcscript".into());
     namespace.append_mcfunction(init);
+    namespace.append_mcfunction(reset);
+    namespace.append_mcfunction(guard);
+    namespace.append_mcfunction(repair);
     namespace.append_mcfunction(pop_frame);
     namespace.append_mcfunction(mov_m_m);
     namespace.append_mcfunction(mov_m_r);
@@ -62,7 +153,14 @@ pub fn mcscript_datapack(path: String) -> Datapack {
     namespace.append_mcfunction(array_pop);
     namespace.append_mcfunction(array_insert);
     namespace.append_mcfunction(array_erase);
+    namespace.append_mcfunction(selector_split);
+    for loop_n in loops {
+        namespace.append_mcfunction(loop_n);
+    }
+    namespace.append_mcfunction(schedule);
+    namespace.append_mcfunction(abort);
     let mut datapack = Datapack::new(path);
+    datapack.set_mc_version(mc_version);
     datapack.append_namespace(namespace);
     datapack
 }
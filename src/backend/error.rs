@@ -1,5 +1,6 @@
 use crate::frontend::ast::{exp::ArrayMethodType, DataType};
 
+#[derive(Debug)]
 pub enum SemanticError {
     MultipleDefinition {
         ident: String,
@@ -52,4 +53,121 @@ pub enum SemanticError {
         begin: usize,
         end: usize,
     },
+    NonConstantExpression {
+        begin: usize,
+        end: usize,
+    },
+    AssignToConstant {
+        ident: String,
+        begin: usize,
+        end: usize,
+    },
+    UnknownAttribute {
+        attribute: String,
+        begin: usize,
+        end: usize,
+    },
+    TaggedFunctionWithParameters {
+        attribute: String,
+        begin: usize,
+        end: usize,
+    },
+    TaskWithReturnValue {
+        begin: usize,
+        end: usize,
+    },
+    RecursiveTask {
+        begin: usize,
+        end: usize,
+    },
+    NotATask {
+        ident: String,
+        begin: usize,
+        end: usize,
+    },
+    DivisionByZero {
+        begin: usize,
+        end: usize,
+    },
+    UnknownExecuteModifier {
+        modifier: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidSelector {
+        message: &'static str,
+        begin: usize,
+        end: usize,
+    },
+    InvalidScoreboardBinding {
+        message: &'static str,
+        begin: usize,
+        end: usize,
+    },
+    UnexpectedStringLiteral {
+        begin: usize,
+        end: usize,
+    },
+    ExpectedStringLiteral {
+        begin: usize,
+        end: usize,
+    },
+    InvalidBlock {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    UnexpectedAttributeArguments {
+        attribute: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidEventHandler {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidResource {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidCommand {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidFormatString {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidTextComponent {
+        message: String,
+        begin: usize,
+        end: usize,
+    },
+    InvalidNbtPath {
+        message: &'static str,
+        begin: usize,
+        end: usize,
+    },
+    NotAllowedInExecuteBlock {
+        statement: String,
+        begin: usize,
+        end: usize,
+    },
+}
+
+#[derive(PartialEq)]
+pub enum Warning {
+    UnreachableCode {
+        begin: usize,
+        end: usize,
+    },
+    UnusedFunction {
+        function: String,
+        begin: usize,
+        end: usize,
+    },
 }
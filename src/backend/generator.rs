@@ -1,44 +1,152 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+};
 
 use crate::{
-    backend::datapack::{Datapack, Mcfunction, Namespace},
-    frontend::ast::{
-        exp::{ArrayMethodType, BinaryOp, Exp, ExpType, UnaryOp},
-        Block, BlockItem, CompileUnit, DataType, FuncDef, FuncParam, GlobalDef, Ident, SrcLocation,
-        Stmt,
+    backend::{
+        command_tree::CommandTree,
+        datapack::{Datapack, Mcfunction, Namespace, Resource, DEFAULT_MC_VERSION},
+    },
+    frontend::{
+        ast::{
+            exp::{ArrayMethodType, BinaryOp, Exp, ExpType, UnaryOp},
+            Block, BlockItem, CompileUnit, DataType, FuncDef, FuncParam, GlobalDef, Ident,
+            SrcLocation, Stmt,
+        },
+        selector::validate_selector,
     },
 };
 
-use super::error::SemanticError;
+use super::error::{SemanticError, Warning};
+
+mod block_registry;
+mod builtins;
+mod call_graph;
+mod checks;
+mod command_validation;
+mod const_eval;
+mod control_flow;
+mod event;
+mod execute;
+mod export;
+mod extern_fn;
+mod inline;
+mod inline_command;
+mod resource;
+mod selector;
+mod tail_call;
+mod task;
+mod text;
+
+use block_registry::BlockRegistry;
+pub use checks::SourceFile;
+
+/// How many iterations of a loop its driver runs before returning to
+/// `mcscript:loop`.
+const LOOP_UNROLL: usize = 4;
+
+/// Replaces the `{}`s in `fmt_str` with the macro arguments `$(0)`, `$(1)`,
+/// ..., and returns how many there were.
+fn macro_command(fmt_str: &str) -> (String, usize) {
+    let mut cmd = fmt_str.to_owned();
+    let mut i = 0;
+    while cmd.contains("{}") {
+        cmd = cmd.replacen("{}", &format!("$({})", i), 1);
+        i += 1;
+    }
+    (cmd, i)
+}
+
+fn validate_score_binding(objective: &str, holder: &str) -> Result<(), &'static str> {
+    if objective.is_empty()
+        || !objective
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-.+".contains(c))
+    {
+        return Err("objective names may only contain letters, digits and \"_-.+\"");
+    }
+    if holder.starts_with('@') {
+        validate_selector(holder)
+    } else if holder.is_empty() || holder == "*" || holder.contains(char::is_whitespace) {
+        Err("invalid score holder")
+    } else {
+        Ok(())
+    }
+}
 
 #[derive(Clone)]
 struct Variable {
     is_local: bool,
     decorated_name: String,
     data_type: DataType,
+    const_value: Option<i32>,
+    /// The holder and objective of a `scoreboard` binding.
+    score: Option<(String, String)>,
 }
 
 impl Variable {
-    pub fn memory_location(&self) -> Location {
-        if self.is_local {
-            Location::Memory(
-                "memory:stack".into(),
-                format!("frame[$(base_index)].{}", self.decorated_name),
-            )
+    pub fn location(&self, frame: &Frame) -> Location {
+        if let Some((holder, objective)) = &self.score {
+            Location::Score(holder.to_owned(), objective.to_owned())
+        } else if self.is_local {
+            match self.data_type {
+                DataType::Int => frame.register(&self.decorated_name),
+                DataType::Array { .. } | DataType::Selector => {
+                    frame.location(&self.decorated_name)
+                }
+            }
         } else {
             Location::Memory("memory:global".into(), format!("{}", self.decorated_name))
         }
     }
 }
 
+/// Where the locals of the function being generated are stored. Both
+/// variants hold the path `<namespace>.<function>` of the function.
+#[derive(Clone)]
+enum Frame {
+    /// `memory:stack frame[$(base_index)]`, needed by functions that may recurse.
+    Stack(String),
+    /// `memory:static <namespace>.<function>`
+    Static(String),
+}
+
+impl Frame {
+    fn location(&self, name: &str) -> Location {
+        match self {
+            Frame::Stack(_) => Location::Memory(
+                "memory:stack".into(),
+                format!("frame[$(base_index)].{}", name),
+            ),
+            Frame::Static(path) => {
+                Location::Memory("memory:static".into(), format!("{}.{}", path, name))
+            }
+        }
+    }
+
+    /// Int locals are kept in a register named after the function, so
+    /// functions that can't reenter each other never share one.
+    fn register(&self, name: &str) -> Location {
+        match self {
+            Frame::Stack(path) | Frame::Static(path) => {
+                Location::Register(format!("{}.{}", path, name))
+            }
+        }
+    }
+}
+
+/// Local scopes, global variables, and the index of the first local scope
+/// that lookups can see.
 struct VariableTable(
     Vec<HashMap<String, Variable>>,
     HashMap<(String, String), Variable>,
+    usize,
 );
 
 impl VariableTable {
     pub fn new() -> Self {
-        Self(vec![HashMap::new()], HashMap::new())
+        Self(vec![HashMap::new()], HashMap::new(), 0)
     }
 
     pub fn enter_scope(&mut self) {
@@ -49,6 +157,17 @@ impl VariableTable {
         self.0.pop();
     }
 
+    /// Makes the current local scopes invisible to lookups, e.g. while the
+    /// body of an inlined function is generated. Returns the previous state
+    /// for `show_outer_scopes`.
+    pub fn hide_outer_scopes(&mut self) -> usize {
+        std::mem::replace(&mut self.2, self.0.len())
+    }
+
+    pub fn show_outer_scopes(&mut self, first_visible_scope: usize) {
+        self.2 = first_visible_scope;
+    }
+
     pub fn new_local_variable(
         &mut self,
         ident: &Ident,
@@ -66,6 +185,8 @@ impl VariableTable {
             is_local: true,
             decorated_name: decorated_name.clone(),
             data_type,
+            const_value: None,
+            score: None,
         };
         self.0
             .last_mut()
@@ -93,11 +214,74 @@ impl VariableTable {
             is_local: false,
             decorated_name,
             data_type,
+            const_value: None,
+            score: None,
         };
         self.1.insert(key, variable.clone());
         Ok(variable)
     }
 
+    pub fn new_score_binding(
+        &mut self,
+        ident: &Ident,
+        namespace: &str,
+        holder: &str,
+        objective: &str,
+    ) -> Result<(), SemanticError> {
+        let key = (ident.string.to_owned(), namespace.to_owned());
+        if self.1.contains_key(&key) {
+            return Err(SemanticError::MultipleDefinition {
+                ident: ident.string.to_owned(),
+                begin: ident.src_loc.begin,
+                end: ident.src_loc.end,
+            });
+        }
+        let variable = Variable {
+            is_local: false,
+            decorated_name: format!("{}@{}", ident.string, namespace),
+            data_type: DataType::Int,
+            const_value: None,
+            score: Some((holder.to_owned(), objective.to_owned())),
+        };
+        self.1.insert(key, variable);
+        Ok(())
+    }
+
+    pub fn new_constant(
+        &mut self,
+        ident: &Ident,
+        namespace: &str,
+        value: i32,
+    ) -> Result<(), SemanticError> {
+        let key = (ident.string.to_owned(), namespace.to_owned());
+        if self.1.contains_key(&key) {
+            return Err(SemanticError::MultipleDefinition {
+                ident: ident.string.to_owned(),
+                begin: ident.src_loc.begin,
+                end: ident.src_loc.end,
+            });
+        }
+        let variable = Variable {
+            is_local: false,
+            decorated_name: format!("{}@{}", ident.string, namespace),
+            data_type: DataType::Int,
+            const_value: Some(value),
+            score: None,
+        };
+        self.1.insert(key, variable);
+        Ok(())
+    }
+
+    /// The int locals in scope, which live in registers.
+    pub fn int_locals(&self) -> Vec<Variable> {
+        self.0
+            .iter()
+            .flat_map(|scope| scope.values())
+            .filter(|variable| variable.is_local && variable.data_type == DataType::Int)
+            .cloned()
+            .collect()
+    }
+
     pub fn set_parameters(&mut self, params: &Vec<FuncParam>) {
         for (i, param) in params.iter().enumerate() {
             let decorated_name = format!("%{}", i);
@@ -107,6 +291,8 @@ impl VariableTable {
                     is_local: true,
                     decorated_name,
                     data_type: param.data_type.clone(),
+                    const_value: None,
+                    score: None,
                 },
             );
         }
@@ -119,7 +305,7 @@ impl VariableTable {
         current_namespace: &str,
     ) -> Result<Variable, SemanticError> {
         if namespace.is_none() {
-            for scope in self.0.iter().rev() {
+            for scope in self.0[self.2..].iter().rev() {
                 if scope.contains_key(&ident.string) {
                     return Ok(scope[&ident.string].clone());
                 }
@@ -201,6 +387,8 @@ enum Location {
     Register(String),
     Memory(String, String),
     MemoryRef(String, String),
+    /// The score of a holder for an objective other than `registers`.
+    Score(String, String),
 }
 
 impl Display for Location {
@@ -209,6 +397,7 @@ impl Display for Location {
             Location::Register(reg) => write!(f, "{}", reg),
             Location::Memory(naid, path) => write!(f, "{} {}", naid, path),
             Location::MemoryRef(naid, path) => write!(f, "{} {}", naid, path),
+            Location::Score(holder, objective) => write!(f, "{} {}", holder, objective),
         }
     }
 }
@@ -240,6 +429,10 @@ impl RegAcc {
         Self { cnt: 0 }
     }
 
+    pub fn starting_at(cnt: u32) -> Self {
+        Self { cnt }
+    }
+
     pub fn new_reg(&mut self) -> Location {
         let reg = Location::Register(format!("r{}", self.cnt));
         self.cnt += 1;
@@ -252,15 +445,12 @@ struct ObjAcc {
 }
 
 impl ObjAcc {
-    pub fn new() -> Self {
-        Self { cnt: 0 }
+    pub fn starting_at(cnt: u32) -> Self {
+        Self { cnt }
     }
 
-    pub fn new_obj(&mut self) -> Location {
-        let obj = Location::Memory(
-            "memory:stack".into(),
-            format!("frame[$(base_index)].%obj{}", self.cnt),
-        );
+    pub fn new_obj(&mut self, frame: &Frame) -> Location {
+        let obj = frame.location(&format!("%obj{}", self.cnt));
         self.cnt += 1;
         obj
     }
@@ -273,10 +463,62 @@ pub struct Generator {
     working_namespace: Option<Namespace>,
     working_function_ident: String,
     working_mcfunction: Option<Mcfunction>,
+    /// The namespace unqualified names are looked up in. It differs from the
+    /// working namespace while a function of another namespace is inlined.
+    scope_namespace: String,
+    frame: Frame,
+    recursive_functions: HashMap<(String, String), usize>,
+    inline_functions: HashSet<(String, String)>,
+    /// The inline functions whose bodies were generated in place of a call.
+    inlined_functions: HashSet<(String, String)>,
+    task_functions: HashSet<(String, String)>,
+    inline_return: Option<inline::InlineReturn>,
+    /// Whether the block about to be generated is the last thing its function
+    /// does, so that a call at its end may reuse the frame.
+    tail_position: bool,
+    register_base: u32,
+    object_base: u32,
     label_acc: u32,
     custom_cmd_acc: u32,
-    break_labels: Vec<String>,
-    continue_labels: Vec<String>,
+    /// How many loops the statement being generated is in, not counting the
+    /// loops around the call of an inlined function.
+    loop_depth: u32,
+    /// The resume labels of the `yield`s and `sleep`s in each loop around the
+    /// statement being generated, after the ones of the function itself.
+    resume_labels: Vec<Vec<Mcfunction>>,
+    /// Whether the statement being generated is in an execute block, not
+    /// counting the ones around the call of an inlined function.
+    in_execute_block: bool,
+    /// The functions that are called from outside the datapack: `init` and
+    /// the `pub` functions.
+    entry_points: Vec<String>,
+    /// The functions that may be called from outside: the `pub`,
+    /// `#[tick]`/`#[load]` and `#[export]` functions, the event handlers and
+    /// the functions named in resources.
+    external_functions: HashSet<(String, String)>,
+    /// The functions that advancements run as rewards, which may happen in
+    /// the middle of any command.
+    reward_functions: HashSet<(String, String)>,
+    /// The resource locations of the `extern fn`s.
+    extern_paths: HashMap<(String, String), String>,
+    warnings: Vec<(String, Warning)>,
+    /// The sources of the namespaces, if runtime checks are enabled.
+    source_files: Option<HashMap<String, SourceFile>>,
+    /// Whether `int` values are returned with `return`, instead of in
+    /// `memory:temp return_value`.
+    native_returns: bool,
+    /// The targeted Minecraft version, which decides the commands allowed in
+    /// `run_command!`.
+    mc_version: (u32, u32, u32),
+    /// The blocks accepted by `set_block`, `fill` and `is_block`.
+    block_registry: BlockRegistry,
+    /// The commands are checked against it, if a command tree is given.
+    command_tree: Option<CommandTree>,
+    /// The custom commands of `run_mod_command!`, which are not checked.
+    mod_commands: HashSet<String>,
+    /// The resources of each namespace.
+    resources: HashMap<String, Vec<Resource>>,
+    event_handlers: Vec<event::EventHandler>,
 }
 
 impl Generator {
@@ -288,13 +530,52 @@ impl Generator {
             working_namespace: None,
             working_function_ident: "".into(),
             working_mcfunction: None,
+            scope_namespace: "".into(),
+            frame: Frame::Static("".into()),
+            recursive_functions: HashMap::new(),
+            inline_functions: HashSet::new(),
+            inlined_functions: HashSet::new(),
+            task_functions: HashSet::new(),
+            inline_return: None,
+            tail_position: false,
+            register_base: 0,
+            object_base: 0,
             label_acc: 0,
             custom_cmd_acc: 0,
-            break_labels: vec![],
-            continue_labels: vec![],
+            loop_depth: 0,
+            resume_labels: vec![],
+            in_execute_block: false,
+            entry_points: vec![],
+            external_functions: HashSet::new(),
+            reward_functions: HashSet::new(),
+            extern_paths: HashMap::new(),
+            warnings: vec![],
+            source_files: None,
+            native_returns: DEFAULT_MC_VERSION >= (1, 20, 3),
+            mc_version: DEFAULT_MC_VERSION,
+            block_registry: BlockRegistry::vanilla(),
+            command_tree: None,
+            mod_commands: HashSet::new(),
+            resources: HashMap::new(),
+            event_handlers: vec![],
         }
     }
 
+    /// Targets Minecraft `version`, 1.21 by default, which must be 1.20.2 or
+    /// later for function macros. Since 1.20.3, functions return `int` values
+    /// with `return`.
+    pub fn set_mc_version(&mut self, version: (u32, u32, u32)) {
+        self.mc_version = version;
+        self.datapack.set_mc_version(version);
+        self.native_returns = version >= (1, 20, 3);
+    }
+
+    /// The warnings found so far, each with the namespace of the source file
+    /// it is in.
+    pub fn warnings(&self) -> &[(String, Warning)] {
+        &self.warnings
+    }
+
     pub fn generate(
         &mut self,
         compile_units: Vec<(CompileUnit, String)>,
@@ -303,10 +584,50 @@ impl Generator {
             self.scan_global_defs(compile_unit, namespace)
                 .map_err(|err| (namespace.to_owned(), err))?;
         }
+        self.resolve_constants(&compile_units)?;
+        self.scan_resources(&compile_units)?;
+        self.resolve_event_handlers()?;
+        self.recursive_functions = call_graph::recursive_components(
+            &compile_units,
+            &self.external_functions,
+            &self.reward_functions,
+        );
+        self.inline_functions = inline::inline_functions(&compile_units, &self.recursive_functions);
+        self.task_functions = task::task_functions(&compile_units, &self.recursive_functions)?;
+        let func_idents = compile_units
+            .iter()
+            .flat_map(|(compile_unit, namespace)| {
+                compile_unit
+                    .global_defs
+                    .iter()
+                    .filter_map(move |global_def| match global_def {
+                        GlobalDef::FuncDef(func_def) => {
+                            Some((namespace.to_owned(), func_def.ident.clone()))
+                        }
+                        _ => None,
+                    })
+            })
+            .collect::<Vec<_>>();
         for (compile_unit, namespace) in compile_units {
             self.generate_from_namespace(compile_unit, namespace.clone())
                 .map_err(|err| (namespace.clone(), err))?;
         }
+        let removed = self.datapack.retain_reachable(&self.entry_points);
+        for (namespace, ident) in func_idents {
+            let inlined = self
+                .inlined_functions
+                .contains(&(namespace.clone(), ident.string.clone()));
+            if !inlined && removed.contains(&format!("{}:{}", namespace, ident.string)) {
+                self.warnings.push((
+                    namespace,
+                    Warning::UnusedFunction {
+                        function: ident.string,
+                        begin: ident.src_loc.begin,
+                        end: ident.src_loc.end,
+                    },
+                ));
+            }
+        }
         Ok(self.datapack.clone())
     }
 
@@ -318,12 +639,92 @@ impl Generator {
         for global_def in &compile_unit.global_defs {
             match global_def {
                 GlobalDef::FuncDef(func_def) => {
+                    for attribute in &func_def.attributes {
+                        let takes_arguments = matches!(
+                            attribute.ident.string.as_str(),
+                            "on" | "on_advancement" | "on_score"
+                        );
+                        if !takes_arguments && !attribute.arguments.is_empty() {
+                            return Err(SemanticError::UnexpectedAttributeArguments {
+                                attribute: attribute.ident.string.to_owned(),
+                                begin: attribute.ident.src_loc.begin,
+                                end: attribute.ident.src_loc.end,
+                            });
+                        }
+                        match attribute.ident.string.as_str() {
+                            "inline" => {}
+                            "on" | "on_advancement" | "on_score" => {
+                                self.scan_event_handler(namespace, func_def, attribute)?;
+                            }
+                            "export" => {
+                                self.entry_points.push(format!(
+                                    "{}:{}",
+                                    namespace,
+                                    export::wrapper_name(&func_def.ident.string)
+                                ));
+                                self.external_functions.insert((
+                                    namespace.to_owned(),
+                                    func_def.ident.string.to_owned(),
+                                ));
+                            }
+                            "tick" | "load" => {
+                                self.external_functions.insert((
+                                    namespace.to_owned(),
+                                    func_def.ident.string.to_owned(),
+                                ));
+                                if !func_def.params.is_empty() {
+                                    return Err(SemanticError::TaggedFunctionWithParameters {
+                                        attribute: attribute.ident.string.to_owned(),
+                                        begin: attribute.ident.src_loc.begin,
+                                        end: attribute.ident.src_loc.end,
+                                    });
+                                }
+                                self.datapack.append_to_function_tag(
+                                    &format!("minecraft:{}", attribute.ident.string),
+                                    format!("{}:{}", namespace, func_def.ident.string),
+                                );
+                            }
+                            _ => {
+                                return Err(SemanticError::UnknownAttribute {
+                                    attribute: attribute.ident.string.to_owned(),
+                                    begin: attribute.ident.src_loc.begin,
+                                    end: attribute.ident.src_loc.end,
+                                })
+                            }
+                        }
+                    }
+                    if func_def.public {
+                        self.entry_points
+                            .push(format!("{}:{}", namespace, func_def.ident.string));
+                        self.external_functions.insert((
+                            namespace.to_owned(),
+                            func_def.ident.string.to_owned(),
+                        ));
+                    }
                     self.function_table.new_function(
                         namespace,
                         &func_def.ident,
                         func_def.clone(),
                     )?;
                 }
+                GlobalDef::ExternFuncDef(extern_func_def) => {
+                    self.function_table.new_function(
+                        namespace,
+                        &extern_func_def.ident,
+                        FuncDef {
+                            attributes: vec![],
+                            public: false,
+                            ident: extern_func_def.ident.clone(),
+                            params: extern_func_def.params.clone(),
+                            block: Block(vec![]),
+                            func_type: extern_func_def.func_type.clone(),
+                        },
+                    )?;
+                    self.extern_paths.insert(
+                        (namespace.to_owned(), extern_func_def.ident.string.to_owned()),
+                        extern_func_def.path.to_owned(),
+                    );
+                }
                 GlobalDef::VariableDef {
                     ident,
                     init_value: _,
@@ -332,6 +733,23 @@ impl Generator {
                     self.variable_table
                         .new_global_variable(ident, namespace, data_type.clone())?;
                 }
+                GlobalDef::ConstDef { .. } | GlobalDef::ResourceDef(_) => {}
+                GlobalDef::ScoreboardDef {
+                    ident,
+                    objective,
+                    holder,
+                    src_loc,
+                } => {
+                    validate_score_binding(objective, holder).map_err(|message| {
+                        SemanticError::InvalidScoreboardBinding {
+                            message,
+                            begin: src_loc.begin,
+                            end: src_loc.end,
+                        }
+                    })?;
+                    self.variable_table
+                        .new_score_binding(ident, namespace, holder, objective)?;
+                }
             }
         }
         Ok(())
@@ -343,19 +761,27 @@ impl Generator {
         namespace: String,
     ) -> Result<(), SemanticError> {
         self.working_namespace = Some(Namespace::new(namespace.clone()));
+        self.scope_namespace = namespace.clone();
+        self.entry_points.push(format!("{}:init", namespace));
 
         // handle global variable definitions
-        let mut init = Mcfunction::new("init".into());
-        init.append_prologue();
-        init.append_command(&format!(
-            "function {}:init-label_0 with storage memory:temp",
-            namespace,
-        ));
-        init.append_epilogue();
-        self.working_namespace().append_mcfunction(init);
         self.working_function_ident = "init".into();
+        self.frame = Frame::Static(format!("{}.init", namespace));
+        self.append_entry(false, false);
         self.label_acc = 0;
         self.working_mcfunction = Some(self.new_label());
+        let mut objectives = HashSet::new();
+        for global_def in &compile_unit.global_defs {
+            if let GlobalDef::ScoreboardDef { objective, .. } = global_def {
+                if objectives.insert(objective) {
+                    self.working_mcfunction().append_command(&format!(
+                        "scoreboard objectives add {} dummy",
+                        objective
+                    ));
+                }
+            }
+        }
+        self.append_event_objectives();
         for global_def in &mut compile_unit.global_defs {
             match global_def {
                 GlobalDef::VariableDef {
@@ -368,7 +794,8 @@ impl Generator {
                         &Some(namespace.clone()),
                         &namespace,
                     )?;
-                    let exp_val = self.eval(init_value, &mut RegAcc::new(), &mut ObjAcc::new())?;
+                    let snapshot = self.command_snapshot();
+                    let exp_val = self.eval(init_value, &mut self.new_reg_acc(), &mut self.new_obj_acc())?;
                     if &exp_val.data_type != data_type {
                         return Err(SemanticError::TypeMismatch {
                             expected_type: data_type.clone(),
@@ -377,7 +804,12 @@ impl Generator {
                             end: init_value.src_loc.end,
                         });
                     }
-                    self.mov(&variable.memory_location(), &exp_val.location);
+                    self.mov(&variable.location(&self.frame), &exp_val.location);
+                    self.validate_commands_since(
+                        snapshot,
+                        init_value.src_loc.begin,
+                        init_value.src_loc.end,
+                    )?;
                 }
                 _ => {}
             }
@@ -395,39 +827,216 @@ impl Generator {
             }
         }
 
+        self.append_event_handlers();
+        for resource in self.resources.remove(&namespace).unwrap_or_default() {
+            self.working_namespace().append_resource(resource);
+        }
         self.datapack
             .append_namespace(self.working_namespace.take().unwrap());
         Ok(())
     }
 
     fn generate_from_func_def(&mut self, func_def: &mut FuncDef) -> Result<(), SemanticError> {
+        let snapshot = self.command_snapshot();
         self.working_function_ident = func_def.ident.string.clone();
-
-        let mut entry = Mcfunction::new(self.working_function_ident.clone());
-        entry.append_prologue();
-        entry.append_command(&format!(
-            "function {}:{}-label_0 with storage memory:temp",
-            self.working_namespace.as_ref().unwrap().name(),
-            self.working_function_ident.clone()
-        ));
-        entry.append_epilogue();
-        self.working_namespace
-            .as_mut()
-            .unwrap()
-            .append_mcfunction(entry);
+        let function_id = (
+            self.working_namespace_name().to_owned(),
+            self.working_function_ident.clone(),
+        );
+        let path = format!("{}.{}", function_id.0, function_id.1);
+        self.frame = if self.recursive_functions.contains_key(&function_id) {
+            Frame::Stack(path)
+        } else {
+            Frame::Static(path)
+        };
+        self.append_entry(
+            !func_def.params.is_empty(),
+            func_def.func_type == Some(DataType::Int),
+        );
+        if func_def
+            .attributes
+            .iter()
+            .any(|attribute| attribute.ident.string == "export")
+        {
+            self.append_export_wrapper(func_def);
+        }
 
         self.label_acc = 0;
         self.working_mcfunction = Some(self.new_label());
         self.variable_table.enter_scope();
         self.variable_table.set_parameters(&func_def.params);
+        self.load_int_locals();
+        self.tail_position = true;
+        self.resume_labels = vec![vec![]];
         self.generate_from_block(&mut func_def.block, &func_def.func_type)?;
+        if self.native_returns
+            && func_def.func_type == Some(DataType::Int)
+            && !control_flow::block_diverges(&func_def.block)
+        {
+            // e.g. a function that leaves its value in `memory:temp
+            // return_value` with `run_command!`
+            self.working_mcfunction()
+                .append_command("return run data get storage memory:temp return_value");
+        }
         self.variable_table.leave_scope();
 
         self.working_namespace
             .as_mut()
             .unwrap()
             .append_mcfunction(self.working_mcfunction.take().unwrap());
-        Ok(())
+        for label_resume in self.resume_labels.pop().unwrap() {
+            self.working_namespace().append_mcfunction(label_resume);
+        }
+        self.validate_commands_since(
+            snapshot,
+            func_def.ident.src_loc.begin,
+            func_def.ident.src_loc.end,
+        )
+    }
+
+    /// Int locals are kept in registers, which all activations of a function
+    /// share. Around a call that may reenter the function being generated,
+    /// they are stored in its frame and loaded back afterwards.
+    fn may_reenter(&self, callee: &(String, String)) -> bool {
+        let current = (
+            self.working_namespace_name().to_owned(),
+            self.working_function_ident.clone(),
+        );
+        match (
+            self.recursive_functions.get(&current),
+            self.recursive_functions.get(callee),
+        ) {
+            (Some(current_component), Some(callee_component)) => {
+                current_component == callee_component
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether `function_id` is an MCScript function and not an `extern fn`.
+    fn is_mcscript_function(&self, function_id: &(String, String)) -> bool {
+        self.function_table.0.contains_key(function_id) && !self.extern_paths.contains_key(function_id)
+    }
+
+    fn store_int_locals(&mut self) {
+        for variable in self.variable_table.int_locals() {
+            self.mov(
+                &self.frame.location(&variable.decorated_name),
+                &variable.location(&self.frame),
+            );
+        }
+    }
+
+    fn load_int_locals(&mut self) {
+        for variable in self.variable_table.int_locals() {
+            self.mov(
+                &variable.location(&self.frame),
+                &self.frame.location(&variable.decorated_name),
+            );
+        }
+    }
+
+    /// Appends the function that callers call. It sets up the frame and jumps
+    /// to the first label.
+    fn append_entry(&mut self, has_params: bool, returns_int: bool) {
+        let namespace = self.working_namespace_name().to_owned();
+        let native_return = self.native_returns && returns_int;
+        let mut entry = Mcfunction::new(self.entry_name(&namespace, &self.working_function_ident));
+        self.append_entry_commands(&mut entry, has_params, native_return.then_some("return run"));
+        if self
+            .external_functions
+            .contains(&(namespace, self.working_function_ident.clone()))
+        {
+            // Calls from outside check the stack first. MCScript code calls
+            // `entry`, as the stack isn't empty during recursive calls.
+            let mut guarded = Mcfunction::new(self.working_function_ident.clone());
+            guarded.append_commands(vec![
+                "function mcscript:guard",
+                "scoreboard players add running registers 1",
+            ]);
+            // callers from outside find the value where they always have
+            self.append_entry_commands(
+                &mut guarded,
+                has_params,
+                native_return
+                    .then_some("execute store result storage memory:temp return_value int 1.0 run"),
+            );
+            guarded.append_command("scoreboard players remove running registers 1");
+            self.working_namespace().append_mcfunction(guarded);
+        }
+        self.working_namespace().append_mcfunction(entry);
+    }
+
+    /// Sets up the frame and calls the first label. With a native return
+    /// value, `hand_over` is put before the command that yields it.
+    fn append_entry_commands(
+        &self,
+        entry: &mut Mcfunction,
+        has_params: bool,
+        hand_over: Option<&str>,
+    ) {
+        let namespace = self.working_namespace_name();
+        if self.source_files.is_some() {
+            entry.append_command("scoreboard players set abort registers 0");
+        }
+        if self
+            .task_functions
+            .contains(&(namespace.to_owned(), self.working_function_ident.clone()))
+        {
+            // a task that is still waiting isn't started again
+            entry.append_command(&format!(
+                "execute if score {} registers matches 1 run return 0",
+                task::running_register(namespace, &self.working_function_ident)
+            ));
+        }
+        match &self.frame {
+            Frame::Stack(_) => {
+                entry.append_prologue();
+                entry.append_command(&format!(
+                    "{}function {}:{}-label_0 with storage memory:temp",
+                    if hand_over.is_some() {
+                        "execute store result score return_value registers run "
+                    } else {
+                        ""
+                    },
+                    namespace,
+                    self.working_function_ident
+                ));
+                entry.append_epilogue();
+                if let Some(hand_over) = hand_over {
+                    entry.append_command(&format!(
+                        "{} scoreboard players get return_value registers",
+                        hand_over
+                    ));
+                }
+            }
+            Frame::Static(path) => {
+                if has_params {
+                    entry.append_command(&format!(
+                        "data modify storage memory:static {} set from storage memory:temp arguments",
+                        path
+                    ));
+                }
+                entry.append_command(&format!(
+                    "{}function {}:{}-label_0",
+                    hand_over.map_or("".into(), |hand_over| format!("{} ", hand_over)),
+                    namespace,
+                    self.working_function_ident
+                ));
+            }
+        }
+    }
+
+    /// The name of the function that MCScript code calls to call `func_ident`.
+    fn entry_name(&self, namespace: &str, func_ident: &str) -> String {
+        if self
+            .external_functions
+            .contains(&(namespace.to_owned(), func_ident.to_owned()))
+        {
+            format!("{}-entry", func_ident)
+        } else {
+            func_ident.to_owned()
+        }
     }
 
     fn generate_from_block(
@@ -435,15 +1044,43 @@ impl Generator {
         block: &mut Block,
         expected_return_type: &Option<DataType>,
     ) -> Result<(), SemanticError> {
-        for block_item in &mut block.0 {
+        let block_in_tail_position = std::mem::take(&mut self.tail_position);
+        let len = block.0.len();
+        let mut diverged = false;
+        // the commands of the previous block item, checked once it is done
+        let mut unchecked = None;
+        for (i, block_item) in block.0.iter_mut().enumerate() {
+            if let Some((snapshot, (begin, end))) = unchecked.take() {
+                self.validate_commands_since(snapshot, begin, end)?;
+            }
+            let tail_position = block_in_tail_position && i + 1 == len;
+            if diverged {
+                if let Some((begin, end)) = control_flow::block_item_span(block_item) {
+                    // bodies of inlined functions are generated once per call
+                    let warning = (
+                        self.scope_namespace.clone(),
+                        Warning::UnreachableCode { begin, end },
+                    );
+                    if !self.warnings.contains(&warning) {
+                        self.warnings.push(warning);
+                    }
+                }
+                break;
+            }
+            if let BlockItem::Stmt(stmt) = block_item {
+                diverged = control_flow::stmt_diverges(stmt);
+            }
+            if let Some(span) = control_flow::block_item_span(block_item) {
+                unchecked = Some((self.command_snapshot(), span));
+            }
             match block_item {
                 BlockItem::Decl(decl) => {
                     let exp_val =
-                        self.eval(&mut decl.init_value, &mut RegAcc::new(), &mut ObjAcc::new())?;
+                        self.eval(&mut decl.init_value, &mut self.new_reg_acc(), &mut self.new_obj_acc())?;
                     let variable = self
                         .variable_table
                         .new_local_variable(&decl.ident, exp_val.data_type)?;
-                    self.mov(&variable.memory_location(), &exp_val.location);
+                    self.mov(&variable.location(&self.frame), &exp_val.location);
                 }
                 BlockItem::Stmt(stmt) => {
                     match stmt {
@@ -451,12 +1088,20 @@ impl Generator {
                             return_value,
                             src_loc,
                         } => {
+                            self.check_not_in_execute_block("return", src_loc)?;
                             if return_value.is_some() {
                                 let return_value = return_value.as_mut().unwrap();
+                                if expected_return_type.is_some()
+                                    && self.is_self_tail_call(return_value)
+                                {
+                                    self.tail_call(return_value)?;
+                                    continue;
+                                }
+                                let mut reg_acc = self.new_reg_acc();
                                 let exp_val = self.eval(
                                     return_value,
-                                    &mut RegAcc::new(),
-                                    &mut ObjAcc::new(),
+                                    &mut reg_acc,
+                                    &mut self.new_obj_acc(),
                                 )?;
                                 if expected_return_type.is_none() {
                                     return Err(SemanticError::ExpectedVoid {
@@ -476,8 +1121,33 @@ impl Generator {
                                         end: return_value.src_loc.end,
                                     });
                                 }
-                                self.mov(&Location::return_value(), &exp_val.location);
-                                self.working_mcfunction().append_command("return 0");
+                                match &self.inline_return {
+                                    Some(inline_return) => {
+                                        let result = inline_return.result.clone().unwrap();
+                                        let exits_label = inline_return.exits_label;
+                                        self.mov(&result, &exp_val.location);
+                                        if exits_label {
+                                            self.append_return();
+                                        }
+                                    }
+                                    None if self.native_returns
+                                        && exp_val.data_type == DataType::Int =>
+                                    {
+                                        let constant = match return_value.exp_type {
+                                            ExpType::Number(value) => Some(value),
+                                            _ => None,
+                                        };
+                                        self.append_native_return(
+                                            &exp_val.location,
+                                            constant,
+                                            &mut reg_acc,
+                                        );
+                                    }
+                                    None => {
+                                        self.mov(&Location::return_value(), &exp_val.location);
+                                        self.append_return();
+                                    }
+                                }
                             } else {
                                 if expected_return_type.is_some() {
                                     return Err(SemanticError::ExpectedValue {
@@ -488,14 +1158,32 @@ impl Generator {
                                         begin: src_loc.begin,
                                         end: src_loc.end,
                                     });
-                                } else {
-                                    self.working_mcfunction().append_command("return 0");
+                                } else if self
+                                    .inline_return
+                                    .as_ref()
+                                    .is_none_or(|inline_return| inline_return.exits_label)
+                                {
+                                    self.append_return();
                                 }
                             }
                         }
                         Stmt::Assign { lhs, new_value } => {
-                            let mut reg_acc = RegAcc::new();
-                            let mut obj_acc = ObjAcc::new();
+                            if let ExpType::Variable { ident, namespace } = &lhs.exp_type {
+                                let variable = self.variable_table.query_variable(
+                                    ident,
+                                    &namespace.as_ref().map(|n| n.string.to_owned()),
+                                    &self.scope_namespace,
+                                )?;
+                                if variable.const_value.is_some() {
+                                    return Err(SemanticError::AssignToConstant {
+                                        ident: ident.string.to_owned(),
+                                        begin: lhs.src_loc.begin,
+                                        end: lhs.src_loc.end,
+                                    });
+                                }
+                            }
+                            let mut reg_acc = self.new_reg_acc();
+                            let mut obj_acc = self.new_obj_acc();
                             let rhs_val = self.eval(new_value, &mut reg_acc, &mut obj_acc)?;
                             let lhs_val = self.eval(lhs, &mut reg_acc, &mut obj_acc)?;
                             if lhs_val.data_type != rhs_val.data_type {
@@ -510,6 +1198,7 @@ impl Generator {
                         }
                         Stmt::Block(block) => {
                             self.variable_table.enter_scope();
+                            self.tail_position = tail_position;
                             self.generate_from_block(block, expected_return_type)?;
                             self.variable_table.leave_scope();
                         }
@@ -518,8 +1207,8 @@ impl Generator {
                             if_branch,
                             else_branch,
                         } => {
-                            let mut reg_acc = RegAcc::new();
-                            let exp_val = self.eval(exp, &mut reg_acc, &mut ObjAcc::new())?;
+                            let mut reg_acc = self.new_reg_acc();
+                            let exp_val = self.eval(exp, &mut reg_acc, &mut self.new_obj_acc())?;
                             if exp_val.data_type != DataType::Int {
                                 return Err(SemanticError::TypeMismatch {
                                     expected_type: DataType::Int,
@@ -531,6 +1220,8 @@ impl Generator {
                             let reg = self.to_reg_readonly(&exp_val.location, &mut reg_acc);
 
                             let namespace = self.working_namespace_name().to_owned();
+                            let call_args = self.label_call_arguments();
+                            let goto = self.goto_command(expected_return_type);
                             let label_if_branch = self.new_label();
                             match else_branch {
                                 Some(else_branch) => {
@@ -538,34 +1229,44 @@ impl Generator {
                                     let label_following = self.new_label();
                                     self.working_mcfunction().append_commands(vec![
                                         &format!(
-                                            "execute if score {} registers matches 0 run return run function {}:{} with storage memory:temp", 
-                                            reg, namespace, label_else_branch.name()
+                                            "execute if score {} registers matches 0 run return run function {}:{}{}", 
+                                            reg, namespace, label_else_branch.name(), call_args
                                         ),
-                                        &format!("function {}:{} with storage memory:temp", namespace, label_if_branch.name()),
+                                        &format!("{} {}:{}{}", goto, namespace, label_if_branch.name(), call_args),
                                     ]);
                                     // if branch
                                     self.work_with_next_mcfunction(label_if_branch);
                                     self.variable_table.enter_scope();
+                                    self.tail_position = tail_position;
                                     self.generate_from_block(if_branch, expected_return_type)?;
                                     self.variable_table.leave_scope();
-                                    self.working_mcfunction().append_command(&format!(
-                                        "function {}:{} with storage memory:temp",
-                                        namespace,
-                                        label_following.name()
-                                    ));
+                                    if !control_flow::block_diverges(if_branch) {
+                                        self.working_mcfunction().append_command(&format!(
+                                            "{} {}:{}{}",
+                                            goto,
+                                            namespace,
+                                            label_following.name(),
+                                            call_args
+                                        ));
+                                    }
                                     // else branch
                                     self.work_with_next_mcfunction(label_else_branch);
                                     self.variable_table.enter_scope();
+                                    self.tail_position = tail_position;
                                     self.generate_from_block(
                                         &mut else_branch.clone(),
                                         expected_return_type,
                                     )?;
                                     self.variable_table.leave_scope();
-                                    self.working_mcfunction().append_command(&format!(
-                                        "function {}:{} with storage memory:temp",
-                                        namespace,
-                                        label_following.name()
-                                    ));
+                                    if !control_flow::block_diverges(else_branch) {
+                                        self.working_mcfunction().append_command(&format!(
+                                            "{} {}:{}{}",
+                                            goto,
+                                            namespace,
+                                            label_following.name(),
+                                            call_args
+                                        ));
+                                    }
                                     // following
                                     self.work_with_next_mcfunction(label_following);
                                 }
@@ -573,47 +1274,39 @@ impl Generator {
                                     let label_following = self.new_label();
                                     self.working_mcfunction().append_commands(vec![
                                         &format!(
-                                            "execute if score {} registers matches 0 run return run function {}:{} with storage memory:temp", 
-                                            reg, namespace, label_following.name()
+                                            "execute if score {} registers matches 0 run return run function {}:{}{}", 
+                                            reg, namespace, label_following.name(), call_args
                                         ),
-                                        &format!("function {}:{} with storage memory:temp", namespace, label_if_branch.name()),
+                                        &format!("{} {}:{}{}", goto, namespace, label_if_branch.name(), call_args),
                                     ]);
                                     // if branch
                                     self.work_with_next_mcfunction(label_if_branch);
                                     self.variable_table.enter_scope();
+                                    self.tail_position = tail_position;
                                     self.generate_from_block(if_branch, expected_return_type)?;
                                     self.variable_table.leave_scope();
-                                    self.working_mcfunction().append_command(&format!(
-                                        "function {}:{} with storage memory:temp",
-                                        namespace,
-                                        label_following.name()
-                                    ));
+                                    if !control_flow::block_diverges(if_branch) {
+                                        self.working_mcfunction().append_command(&format!(
+                                            "{} {}:{}{}",
+                                            goto,
+                                            namespace,
+                                            label_following.name(),
+                                            call_args
+                                        ));
+                                    }
                                     // following
                                     self.work_with_next_mcfunction(label_following);
                                 }
                             }
                         }
                         Stmt::While { exp, body } => {
-                            let label_judge = self.new_label();
-                            let label_while_body = self.new_label();
-                            let label_following = self.new_label();
-
-                            let label_judge_name = label_judge.name().to_owned();
-                            let namespace = self.working_namespace_name().to_owned();
-
-                            self.break_labels.push(label_following.name().to_owned());
-                            self.continue_labels.push(label_judge.name().to_owned());
+                            let label_iteration = self.new_label();
+                            let iteration_name = label_iteration.name().to_owned();
 
-                            self.working_mcfunction().append_commands(vec![&format!(
-                                "function {}:{} with storage memory:temp",
-                                namespace,
-                                label_judge.name()
-                            )]);
-
-                            // judge
-                            self.work_with_next_mcfunction(label_judge);
-                            let mut reg_acc = RegAcc::new();
-                            let exp_val = self.eval(exp, &mut reg_acc, &mut ObjAcc::new())?;
+                            // iteration
+                            let caller = self.working_mcfunction.replace(label_iteration);
+                            let mut reg_acc = self.new_reg_acc();
+                            let exp_val = self.eval(exp, &mut reg_acc, &mut self.new_obj_acc())?;
                             if exp_val.data_type != DataType::Int {
                                 return Err(SemanticError::TypeMismatch {
                                     expected_type: DataType::Int,
@@ -623,102 +1316,122 @@ impl Generator {
                                 });
                             }
                             let reg = self.to_reg_readonly(&exp_val.location, &mut reg_acc);
-                            self.working_mcfunction().append_commands(vec![
-                                &format!(
-                                    "execute if score {} registers matches 0 run return run function {}:{} with storage memory:temp", 
-                                    reg, namespace, label_following.name()
-                                ),
-                                &format!("function {}:{} with storage memory:temp", namespace, label_while_body.name()),
-                            ]);
-                            // while body
-                            self.work_with_next_mcfunction(label_while_body);
+                            self.working_mcfunction().append_command(&format!(
+                                "execute if score {} registers matches 0 run return run scoreboard players set loop_state registers 1",
+                                reg
+                            ));
+                            self.loop_depth += 1;
+                            self.resume_labels.push(vec![]);
                             self.variable_table.enter_scope();
                             self.generate_from_block(body, expected_return_type)?;
                             self.variable_table.leave_scope();
-                            self.working_mcfunction().append_commands(vec![
-                                "",
-                                &format!(
-                                    "function {}:{} with storage memory:temp",
-                                    namespace, label_judge_name
-                                ),
-                            ]);
-                            // following
-                            self.break_labels.pop();
-                            self.continue_labels.pop();
-                            self.work_with_next_mcfunction(label_following);
+                            self.loop_depth -= 1;
+                            if !control_flow::block_diverges(body)
+                                && (control_flow::exp_may_run_loop(exp)
+                                    || control_flow::block_may_run_loop(body))
+                            {
+                                self.working_mcfunction()
+                                    .append_command("scoreboard players set loop_state registers 0");
+                            }
+                            let last_label = std::mem::replace(&mut self.working_mcfunction, caller);
+                            self.working_namespace()
+                                .append_mcfunction(last_label.unwrap());
+
+                            let driver = self.append_loop(&iteration_name);
+                            if control_flow::block_may_suspend(body) {
+                                // following, where the resume labels go on after the loop
+                                let label_following = self.new_label();
+                                let namespace = self.working_namespace_name().to_owned();
+                                let call_args = self.label_call_arguments();
+                                self.working_mcfunction().append_commands(vec![
+                                    "execute unless score loop_state registers matches 1 run return 0",
+                                    &format!(
+                                        "function {}:{}{}",
+                                        namespace,
+                                        label_following.name(),
+                                        call_args
+                                    ),
+                                ]);
+                                self.resume_after_loop(&driver, label_following.name());
+                                self.work_with_next_mcfunction(label_following);
+                            } else {
+                                self.resume_labels.pop();
+                                if control_flow::block_contains_return(body) {
+                                    // the value of a `return` in the loop is
+                                    // returned once out of all loops
+                                    let command = if self.native_returns
+                                        && self.loop_depth == 0
+                                        && self.inline_return.is_none()
+                                        && *expected_return_type == Some(DataType::Int)
+                                    {
+                                        "execute if score loop_state registers matches 2 run return run scoreboard players get return_value registers"
+                                    } else {
+                                        "execute if score loop_state registers matches 2 run return 0"
+                                    };
+                                    self.working_mcfunction().append_command(command);
+                                }
+                            }
                         }
                         Stmt::Exp(exp) => {
-                            self.eval(exp, &mut RegAcc::new(), &mut ObjAcc::new())?;
+                            if tail_position
+                                && expected_return_type.is_none()
+                                && self.is_self_tail_call(exp)
+                            {
+                                self.tail_call(exp)?;
+                                continue;
+                            }
+                            self.eval(exp, &mut self.new_reg_acc(), &mut self.new_obj_acc())?;
                         }
                         Stmt::Break {
                             src_loc: SrcLocation { begin, end },
                         } => {
-                            if self.break_labels.is_empty() {
+                            if self.loop_depth == 0 {
                                 return Err(SemanticError::NoLoopToBreak {
                                     begin: *begin,
                                     end: *end,
                                 });
                             }
-                            let break_label = self.break_labels.last().unwrap().clone();
-                            let namespace = self.working_namespace_name().to_owned();
-                            self.working_mcfunction().append_command(&format!(
-                                "return run function {}:{} with storage memory:temp",
-                                namespace, break_label
-                            ));
+                            self.working_mcfunction().append_command(
+                                "return run scoreboard players set loop_state registers 1",
+                            );
                         }
                         Stmt::Continue {
                             src_loc: SrcLocation { begin, end },
                         } => {
-                            if self.continue_labels.is_empty() {
+                            if self.loop_depth == 0 {
                                 return Err(SemanticError::NoLoopToContinue {
                                     begin: *begin,
                                     end: *end,
                                 });
                             }
-                            let continue_label = self.continue_labels.last().unwrap().clone();
-                            let namespace = self.working_namespace_name().to_owned();
-                            self.working_mcfunction().append_command(&format!(
-                                "return run function {}:{} with storage memory:temp",
-                                namespace, continue_label
-                            ));
+                            self.working_mcfunction().append_command(
+                                "return run scoreboard players set loop_state registers 0",
+                            );
                         }
-                        Stmt::InlineCommand { is_mod_command, fmt_str, arguments } => {
-                            for (i, arg) in arguments.iter_mut().enumerate() {
-                                let exp_val =
-                                    self.eval(arg, &mut RegAcc::new(), &mut ObjAcc::new())?;
-                                self.mov(
-                                    &Location::Memory(
-                                        "memory:temp".into(),
-                                        format!("custom_command_arguments.{}", i),
-                                    ),
-                                    &exp_val.location,
-                                );
-                            }
-                            let mut custom_cmd =
-                                Mcfunction::new(format!("custom_cmd_{}", self.custom_cmd_acc));
-                            self.custom_cmd_acc += 1;
-                            let mut cmd = fmt_str.to_owned();
-                            let mut i: i32 = 0;
-                            while cmd.contains("{}") {
-                                cmd = cmd.replacen("{}", &format!("$({})", i), 1);
-                                i += 1;
-                            }
-                            if *is_mod_command && i == 0 {
-                                cmd.push_str("$(empty_str)");
-                            }
-                            custom_cmd.append_command(&cmd);
-                            let custom_cmd_name = custom_cmd.name().to_owned();
-                            let namespace = self.working_namespace_name().to_owned();
-                            self.working_namespace().append_mcfunction(custom_cmd);
-                            self.working_mcfunction().append_commands(vec![&format!(
-                                "function {}:{} with storage memory:temp custom_command_arguments",
-                                namespace, custom_cmd_name
-                            )]);
+                        Stmt::Yield { src_loc } => {
+                            self.check_not_in_execute_block("yield", src_loc)?;
+                            self.suspend(None)?
+                        }
+                        Stmt::Sleep { ticks, src_loc } => {
+                            self.check_not_in_execute_block("sleep", src_loc)?;
+                            self.suspend(Some(ticks))?
                         }
+                        Stmt::Execute {
+                            modifiers, body, ..
+                        } => self.execute_block(modifiers, body)?,
+                        Stmt::InlineCommand {
+                            is_mod_command,
+                            fmt_str,
+                            arguments,
+                            src_loc,
+                        } => self.inline_command(*is_mod_command, fmt_str, arguments, src_loc)?,
                     }
                 }
             }
         }
+        if let Some((snapshot, (begin, end))) = unchecked {
+            self.validate_commands_since(snapshot, begin, end)?;
+        }
         Ok(())
     }
 
@@ -728,6 +1441,9 @@ impl Generator {
         reg_acc: &mut RegAcc,
         obj_acc: &mut ObjAcc,
     ) -> Result<ExpVal, SemanticError> {
+        if let Some(value) = self.const_eval(exp, &self.scope_namespace)? {
+            exp.exp_type = ExpType::Number(value);
+        }
         match &mut exp.exp_type {
             ExpType::Number(num) => {
                 let reg_res = reg_acc.new_reg();
@@ -741,24 +1457,20 @@ impl Generator {
                 let variable = self.variable_table.query_variable(
                     ident,
                     &namespace.as_ref().map(|n| n.string.to_owned()),
-                    self.working_namespace_name(),
+                    &self.scope_namespace,
                 )?;
                 Ok(ExpVal {
                     data_type: variable.data_type.clone(),
-                    location: variable.memory_location(),
+                    location: variable.location(&self.frame),
                 })
             }
             ExpType::UnaryExp(op, exp) => {
                 let exp_val = self.eval(exp, reg_acc, obj_acc)?;
                 if let DataType::Int = exp_val.data_type {
-                    let reg_exp = reg_acc.new_reg();
-                    self.mov(&reg_exp, &exp_val.location);
                     match op {
-                        UnaryOp::Positive => Ok(ExpVal {
-                            data_type: DataType::Int,
-                            location: reg_exp,
-                        }),
+                        UnaryOp::Positive => Ok(exp_val),
                         UnaryOp::Negative => {
+                            let reg_exp = self.to_reg_readonly(&exp_val.location, reg_acc);
                             let reg_res = reg_acc.new_reg();
                             self.mov_immediate(&reg_res, "0", obj_acc);
                             self.working_mcfunction().append_command(&format!(
@@ -771,6 +1483,7 @@ impl Generator {
                             })
                         }
                         UnaryOp::Not => {
+                            let reg_exp = self.to_reg_readonly(&exp_val.location, reg_acc);
                             let reg_res = reg_acc.new_reg();
                             self.mov_immediate(&reg_res, "0", obj_acc);
                             self.working_mcfunction().append_command(&format!(
@@ -825,6 +1538,11 @@ impl Generator {
                                 location: reg_res,
                             });
                         } else {
+                            if let BinaryOp::Div | BinaryOp::Mod = op {
+                                if !matches!(rhs.exp_type, ExpType::Number(_)) {
+                                    self.check_divisor(&reg_rhs, &rhs.src_loc);
+                                }
+                            }
                             let (op, is_rel, is_ne) = match op {
                                 BinaryOp::Add => ("+=", false, false),
                                 BinaryOp::Sub => ("-=", false, false),
@@ -895,7 +1613,25 @@ impl Generator {
             } => {
                 let namespace = match namespace {
                     Some(namespace) => namespace.string.to_owned(),
-                    None => self.working_namespace_name().to_owned(),
+                    None => {
+                        // functions of the namespace hide the builtins
+                        if self
+                            .function_table
+                            .query_function(&self.scope_namespace, func_ident)
+                            .is_err()
+                        {
+                            if let Some(result) = self.builtin_call(
+                                func_ident,
+                                arguments,
+                                &exp.src_loc,
+                                reg_acc,
+                                obj_acc,
+                            ) {
+                                return result;
+                            }
+                        }
+                        self.scope_namespace.clone()
+                    }
                 };
 
                 let func_def = self
@@ -912,13 +1648,26 @@ impl Generator {
                     });
                 }
 
+                if self
+                    .inline_functions
+                    .contains(&(namespace.clone(), func_ident.string.clone()))
+                {
+                    self.inlined_functions
+                        .insert((namespace.clone(), func_ident.string.clone()));
+                    return self.inline_call(&namespace, func_def, arguments, reg_acc, obj_acc);
+                }
+                if let Some(path) = self
+                    .extern_paths
+                    .get(&(namespace.clone(), func_ident.string.clone()))
+                {
+                    let path = path.to_owned();
+                    return self.extern_call(&path, func_def, arguments, reg_acc, obj_acc);
+                }
+
                 // save registers
                 for i in 0..reg_acc.cnt {
                     self.mov(
-                        &Location::Memory(
-                            "memory:stack".into(),
-                            format!("frame[$(base_index)].%r{}", i),
-                        ),
+                        &self.frame.location(&format!("%r{}", i)),
                         &Location::Register(format!("r{}", i)),
                     );
                 }
@@ -937,22 +1686,39 @@ impl Generator {
                     self.mov(&Location::argument(i as u32), &exp_val.location);
                 }
                 // call function
-                self.working_mcfunction().append_command(&format!(
-                    "function {}:{} with storage memory:temp",
-                    namespace, func_ident.string
-                ));
+                let may_reenter = self.may_reenter(&(namespace.clone(), func_ident.string.clone()));
+                if may_reenter {
+                    self.store_int_locals();
+                }
+                let saved_cnt = reg_acc.cnt;
+                let entry_name = self.entry_name(&namespace, &func_ident.string);
+                let native_result = (self.native_returns
+                    && func_def.func_type == Some(DataType::Int))
+                .then(|| reg_acc.new_reg());
+                let store = match &native_result {
+                    Some(reg) => format!("execute store result score {} registers run ", reg),
+                    None => "".into(),
+                };
+                self.working_mcfunction()
+                    .append_command(&format!("{}function {}:{}", store, namespace, entry_name));
+                self.append_abort_check();
+                if may_reenter {
+                    self.load_int_locals();
+                }
                 // restore registers
-                for i in 0..reg_acc.cnt {
+                for i in 0..saved_cnt {
                     self.mov(
                         &Location::Register(format!("r{}", i)),
-                        &Location::Memory(
-                            "memory:stack".into(),
-                            format!("frame[$(base_index)].%r{}", i),
-                        ),
+                        &self.frame.location(&format!("%r{}", i)),
                     );
                 }
                 // store return value
-                if func_def.func_type.is_some() {
+                if let Some(reg_res) = native_result {
+                    Ok(ExpVal {
+                        data_type: DataType::Int,
+                        location: reg_res,
+                    })
+                } else if func_def.func_type.is_some() {
                     let data_type = func_def.func_type.as_ref().unwrap();
                     match data_type {
                         DataType::Int => {
@@ -963,8 +1729,8 @@ impl Generator {
                                 location: reg_res,
                             })
                         }
-                        DataType::Array { element_type: _ } => {
-                            let obj_res = obj_acc.new_obj();
+                        DataType::Array { .. } | DataType::Selector => {
+                            let obj_res = obj_acc.new_obj(&self.frame);
                             self.mov(&obj_res, &Location::return_value());
                             Ok(ExpVal {
                                 data_type: data_type.clone(),
@@ -980,14 +1746,6 @@ impl Generator {
                 }
             }
             ExpType::NewArray { length, element } => {
-                let label_judge = self.new_label();
-                let label_while_body = self.new_label();
-                let label_following = self.new_label();
-
-                let label_judge_name = label_judge.name().to_owned();
-
-                let namespace = self.working_namespace_name().to_owned();
-
                 let length_val = self.eval(length, reg_acc, obj_acc)?;
                 let element_val = self.eval(element, reg_acc, obj_acc)?;
 
@@ -1002,43 +1760,29 @@ impl Generator {
 
                 let reg_len = self.to_reg_readonly(&length_val.location, reg_acc);
                 let reg_current_len = reg_acc.new_reg();
-                let arr = obj_acc.new_obj();
+                let arr = obj_acc.new_obj(&self.frame);
                 self.mov_immediate(&arr, "[]", obj_acc);
                 self.mov_immediate(&reg_current_len, "0", obj_acc);
-                self.working_mcfunction().append_command(&format!(
-                    "function {}:{} with storage memory:temp",
-                    namespace,
-                    label_judge.name(),
-                ));
-                // judge
-                self.work_with_next_mcfunction(label_judge);
-                self.working_mcfunction().append_commands(vec![
-                    &format!(
-                        "execute if score {} registers >= {} registers run return run function {}:{} with storage memory:temp", 
-                        reg_current_len, reg_len, namespace, label_following.name()
-                    ),
-                    &format!("function {}:{} with storage memory:temp", namespace, label_while_body.name()),
-                ]);
-                // append
-                self.work_with_next_mcfunction(label_while_body);
                 self.mov(
                     &Location::Memory("memory:temp".into(), "element".into()),
                     &element_val.location,
                 );
-                self.working_mcfunction().append_command(&format!(
-                    "data modify storage {} append from storage memory:temp element",
-                    arr
-                ));
-                self.working_mcfunction().append_commands(vec![
-                    &format!("scoreboard players add {} registers 1", reg_current_len),
-                    "",
+                // append
+                let mut label_iteration = self.new_label();
+                label_iteration.append_commands(vec![
                     &format!(
-                        "function {}:{} with storage memory:temp",
-                        namespace, label_judge_name
+                        "execute if score {} registers >= {} registers run return run scoreboard players set loop_state registers 1",
+                        reg_current_len, reg_len
                     ),
+                    &format!(
+                        "data modify storage {} append from storage memory:temp element",
+                        arr
+                    ),
+                    &format!("scoreboard players add {} registers 1", reg_current_len),
                 ]);
-                // following
-                self.work_with_next_mcfunction(label_following);
+                let iteration_name = label_iteration.name().to_owned();
+                self.working_namespace().append_mcfunction(label_iteration);
+                self.append_loop(&iteration_name);
                 Ok(ExpVal {
                     data_type: DataType::Array {
                         element_type: Box::new(element_val.data_type),
@@ -1049,11 +1793,15 @@ impl Generator {
             ExpType::ArrayElement { array, subscript } => {
                 self.get_element(array, subscript, reg_acc, obj_acc)
             }
+            ExpType::Running {
+                namespace,
+                func_ident,
+            } => self.eval_running(namespace, func_ident, reg_acc),
             ExpType::SquareBracketsArray {
                 element_type,
                 elements,
             } => {
-                let arr = obj_acc.new_obj();
+                let arr = obj_acc.new_obj(&self.frame);
                 self.mov_immediate(&arr, "[]", obj_acc);
 
                 if elements.is_empty() {
@@ -1117,9 +1865,68 @@ impl Generator {
             ExpType::ArrayMethod { array, method } => {
                 self.handle_array_method(array, method, reg_acc, obj_acc)
             }
+            ExpType::StringLiteral(_) => Err(SemanticError::UnexpectedStringLiteral {
+                begin: exp.src_loc.begin,
+                end: exp.src_loc.end,
+            }),
+            ExpType::Selector(selector) => Ok(self.eval_selector(selector, obj_acc)),
+            ExpType::SelectorWith {
+                selector,
+                argument,
+                arguments,
+            } => self.eval_selector_with(
+                selector,
+                argument,
+                arguments,
+                &exp.src_loc,
+                reg_acc,
+                obj_acc,
+            ),
         }
     }
 
+    /// Runs `command`, where `$(i)` stands for the value at `arguments[i]`.
+    /// Without arguments, it is run directly.
+    fn append_macro_command(&mut self, command: &str, arguments: &[Location]) {
+        if arguments.is_empty() {
+            self.working_mcfunction().append_command(command);
+            return;
+        }
+        // the custom command isn't called with `memory:temp`, so the base
+        // index of the stack frame is passed on as one more argument
+        let mut command = command.to_owned();
+        let mut arguments = arguments.to_vec();
+        if command.contains("$(base_index)") {
+            command = command.replace("$(base_index)", &format!("$({})", arguments.len()));
+            arguments.push(Location::Memory("memory:temp".into(), "base_index".into()));
+        }
+        for (i, argument) in arguments.iter().enumerate() {
+            self.mov(
+                &Location::Memory(
+                    "memory:temp".into(),
+                    format!("custom_command_arguments.{}", i),
+                ),
+                argument,
+            );
+        }
+        let custom_cmd = self.append_custom_command(&command);
+        self.working_mcfunction().append_command(&format!(
+            "function {} with storage memory:temp custom_command_arguments",
+            custom_cmd
+        ));
+    }
+
+    /// Adds a function that runs the macro line `command` and returns its
+    /// resource location.
+    fn append_custom_command(&mut self, command: &str) -> String {
+        let mut custom_cmd = Mcfunction::new(format!("custom_cmd_{}", self.custom_cmd_acc));
+        self.custom_cmd_acc += 1;
+        custom_cmd.append_command(command);
+        let name = format!("{}:{}", self.working_namespace_name(), custom_cmd.name());
+        self.working_namespace().append_mcfunction(custom_cmd);
+        name
+    }
+
     fn get_element(
         &mut self,
         array: &mut Exp,
@@ -1139,6 +1946,14 @@ impl Generator {
 
         let arr_val = self.eval(array, reg_acc, obj_acc)?;
         if let DataType::Array { element_type } = arr_val.data_type {
+            self.check_index(
+                &arr_val.location,
+                &subscript_val.location,
+                false,
+                &subscript.src_loc,
+                reg_acc,
+                obj_acc,
+            );
             self.mov(
                 &Location::Memory("memory:temp".into(), "subscript".into()),
                 &subscript_val.location,
@@ -1160,7 +1975,7 @@ impl Generator {
             }
             self.working_mcfunction()
                 .append_command("function mcscript:load_element_path with storage memory:temp");
-            let element_location = obj_acc.new_obj();
+            let element_location = obj_acc.new_obj(&self.frame);
             self.mov(
                 &element_location,
                 &Location::Memory("memory:temp".into(), "element_path".into()),
@@ -1189,28 +2004,7 @@ impl Generator {
         match &arr_val.data_type {
             DataType::Array { element_type } => match method {
                 ArrayMethodType::Size => {
-                    let reg_res = reg_acc.new_reg();
-                    match arr_val.location {
-                        Location::Memory(_, _) => {
-                            self.working_mcfunction().append_command(&format!(
-                                "execute store result score {} registers run data get storage {}",
-                                reg_res, arr_val.location
-                            ));
-                        }
-                        Location::MemoryRef(_, _) => {
-                            self.mov_immediate(
-                                &Location::Memory("memory:temp".into(), "target_reg".into()),
-                                &format!("{}", reg_res),
-                                obj_acc,
-                            );
-                            self.working_mcfunction().append_commands(vec![&format!(
-                                "data modify storage memory:temp array_path set from storage {}", arr_val.location
-                            ),
-                            "function mcscript:load_array_size with storage memory:temp"
-                            ]);
-                        }
-                        _ => unreachable!(),
-                    }
+                    let reg_res = self.load_array_size(&arr_val.location, reg_acc, obj_acc);
                     return Ok(ExpVal {
                         data_type: DataType::Int,
                         location: reg_res,
@@ -1253,6 +2047,7 @@ impl Generator {
                     });
                 }
                 ArrayMethodType::Pop => {
+                    self.check_not_empty(&arr_val.location, &array.src_loc, reg_acc, obj_acc);
                     match arr_val.location {
                         Location::Memory(_, _) => {
                             self.working_mcfunction().append_command(&format!(
@@ -1284,6 +2079,14 @@ impl Generator {
                             end: pos.src_loc.end,
                         });
                     }
+                    self.check_index(
+                        &arr_val.location,
+                        &pos_val.location,
+                        true,
+                        &pos.src_loc,
+                        reg_acc,
+                        obj_acc,
+                    );
                     self.mov(
                         &Location::Memory("memory:temp".into(), "subscript".into()),
                         &pos_val.location,
@@ -1333,6 +2136,14 @@ impl Generator {
                             end: pos.src_loc.end,
                         });
                     }
+                    self.check_index(
+                        &arr_val.location,
+                        &pos_val.location,
+                        false,
+                        &pos.src_loc,
+                        reg_acc,
+                        obj_acc,
+                    );
                     self.mov(
                         &Location::Memory("memory:temp".into(), "subscript".into()),
                         &pos_val.location,
@@ -1371,6 +2182,37 @@ impl Generator {
         }
     }
 
+    fn load_array_size(
+        &mut self,
+        array: &Location,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Location {
+        let reg_res = reg_acc.new_reg();
+        match array {
+            Location::Memory(_, _) => {
+                self.working_mcfunction().append_command(&format!(
+                    "execute store result score {} registers run data get storage {}",
+                    reg_res, array
+                ));
+            }
+            Location::MemoryRef(_, _) => {
+                self.mov_immediate(
+                    &Location::Memory("memory:temp".into(), "target_reg".into()),
+                    &format!("{}", reg_res),
+                    obj_acc,
+                );
+                self.working_mcfunction().append_commands(vec![&format!(
+                    "data modify storage memory:temp array_path set from storage {}", array
+                ),
+                "function mcscript:load_array_size with storage memory:temp"
+                ]);
+            }
+            _ => unreachable!(),
+        }
+        reg_res
+    }
+
     fn mov(&mut self, dest: &Location, src: &Location) {
         match src {
             Location::Register(reg_src) => match dest {
@@ -1388,6 +2230,10 @@ impl Generator {
                         "function mcscript:mov_m_r with storage memory:temp",
                     ])
                 }
+                Location::Score(holder, objective) => self.working_mcfunction().append_command(&format!(
+                    "scoreboard players operation {} {} = {} registers",
+                    holder, objective, reg_src
+                )),
             },
             Location::Memory(naid_src, path_src) => match dest {
                 Location::Register(reg_dest) => self.working_mcfunction().append_command(
@@ -1398,7 +2244,10 @@ impl Generator {
                     &format!("data modify storage memory:temp target_path set from storage {} {}", loc_naid_dest, loc_path_dest),
                     &format!("data modify storage memory:temp src_path set value \"{} {}\"", naid_src, path_src),
                     "function mcscript:mov_m_m with storage memory:temp",
-                ])
+                ]),
+                Location::Score(holder, objective) => self.working_mcfunction().append_command(
+                    &format!("execute store result score {} {} run data get storage {} {} 1.0", holder, objective, naid_src, path_src
+                )),
             },
             Location::MemoryRef(loc_naid_src, loc_path_src) => match dest {
                 Location::Register(reg_dest) => self.working_mcfunction().append_commands(vec![
@@ -1416,7 +2265,30 @@ impl Generator {
                     &format!("data modify storage memory:temp src_path set from storage {} {}", loc_naid_src, loc_path_src),
                     "function mcscript:mov_m_m with storage memory:temp",
                 ]),
-            }
+                Location::Score(..) => {
+                    let score = Location::Register("%score".into());
+                    self.mov(&score, src);
+                    self.mov(dest, &score);
+                }
+            },
+            Location::Score(holder_src, objective_src) => match dest {
+                Location::Register(reg_dest) => self.working_mcfunction().append_command(&format!(
+                    "scoreboard players operation {} registers = {} {}",
+                    reg_dest, holder_src, objective_src
+                )),
+                Location::Memory(naid, path) => self.working_mcfunction().append_command(
+                    &format!("execute store result storage {} {} int 1.0 run scoreboard players get {} {}", naid, path, holder_src, objective_src
+                )),
+                Location::MemoryRef(..) => {
+                    let score = Location::Register("%score".into());
+                    self.mov(&score, src);
+                    self.mov(dest, &score);
+                }
+                Location::Score(holder_dest, objective_dest) => self.working_mcfunction().append_command(&format!(
+                    "scoreboard players operation {} {} = {} {}",
+                    holder_dest, objective_dest, holder_src, objective_src
+                )),
+            },
         }
     }
 
@@ -1433,10 +2305,16 @@ impl Generator {
                 ))
             }
             Location::MemoryRef(_, _) => {
-                let obj = obj_acc.new_obj();
+                let obj = obj_acc.new_obj(&self.frame);
                 self.mov_immediate(&obj, src, obj_acc);
                 self.mov(dest, &obj);
             }
+            Location::Score(holder, objective) => {
+                self.working_mcfunction().append_command(&format!(
+                    "scoreboard players set {} {} {}",
+                    holder, objective, src
+                ))
+            }
         }
     }
 
@@ -1451,6 +2329,25 @@ impl Generator {
         }
     }
 
+    /// Locals in a static frame are not accessed through `$(base_index)`, so
+    /// the labels of such functions are not macro functions.
+    fn label_call_arguments(&self) -> &'static str {
+        match self.frame {
+            Frame::Stack(_) => " with storage memory:temp",
+            Frame::Static(_) => "",
+        }
+    }
+
+    /// Temporaries of a statement start after the ones still in use by the
+    /// expression an inlined function is called from.
+    fn new_reg_acc(&self) -> RegAcc {
+        RegAcc::starting_at(self.register_base)
+    }
+
+    fn new_obj_acc(&self) -> ObjAcc {
+        ObjAcc::starting_at(self.object_base)
+    }
+
     fn working_mcfunction(&mut self) -> &mut Mcfunction {
         self.working_mcfunction.as_mut().unwrap()
     }
@@ -1469,6 +2366,79 @@ impl Generator {
         Mcfunction::new(mcfunction_name)
     }
 
+    /// Runs the label `iteration` until it sets `loop_state` to non-zero.
+    /// The driver runs a few iterations in a row and `mcscript:loop` calls
+    /// the driver again and again, so the call depth doesn't grow with the
+    /// number of iterations. If `mcscript:loop` runs out of calls, it aborts,
+    /// and the label is left even without `--checked`.
+    fn append_loop(&mut self, iteration: &str) -> String {
+        let namespace = self.working_namespace_name().to_owned();
+        let call_args = self.label_call_arguments();
+        let mut driver = self.new_label();
+        driver.append_command(&format!("function {}:{}{}", namespace, iteration, call_args));
+        for _ in 1..LOOP_UNROLL {
+            driver.append_command(&format!(
+                "execute if score loop_state registers matches 0 run function {}:{}{}",
+                namespace, iteration, call_args
+            ));
+        }
+        let driver_name = format!("{}:{}", namespace, driver.name());
+        self.working_namespace().append_mcfunction(driver);
+        self.working_mcfunction().append_commands(vec![
+            "scoreboard players set loop_state registers 0",
+            &format!("function mcscript:loop {{driver: \"{}\"}}", driver_name),
+            "execute if score abort registers matches 1 run return 0",
+        ]);
+        driver_name
+    }
+
+    /// Leaves the function, or the label of an inlined body. Inside a loop,
+    /// `loop_state` also tells the loops to stop and return.
+    /// Returns `value` as the return value of the function's command, which
+    /// the caller stores with `execute store result`. Inside loops, the value
+    /// waits in `return_value registers` until the loops have returned.
+    fn append_native_return(
+        &mut self,
+        value: &Location,
+        constant: Option<i32>,
+        reg_acc: &mut RegAcc,
+    ) {
+        if self.loop_depth > 0 {
+            self.mov(&Location::Register("return_value".into()), value);
+            self.append_return();
+        } else if let Some(value) = constant {
+            self.working_mcfunction()
+                .append_command(&format!("return {}", value));
+        } else {
+            let reg = self.to_reg_readonly(value, reg_acc);
+            self.working_mcfunction()
+                .append_command(&format!("return run scoreboard players get {} registers", reg));
+        }
+    }
+
+    /// How a label calls the label that ends it. If the value of a native
+    /// `return` may come back from the callee, the call has to pass it on.
+    fn goto_command(&self, expected_return_type: &Option<DataType>) -> &'static str {
+        if self.native_returns
+            && *expected_return_type == Some(DataType::Int)
+            && self.loop_depth == 0
+            && self.inline_return.is_none()
+        {
+            "return run function"
+        } else {
+            "function"
+        }
+    }
+
+    fn append_return(&mut self) {
+        let command = if self.loop_depth > 0 {
+            "return run scoreboard players set loop_state registers 2"
+        } else {
+            "return 0"
+        };
+        self.working_mcfunction().append_command(command);
+    }
+
     fn work_with_next_mcfunction(&mut self, next_mcfunction: Mcfunction) {
         self.working_namespace
             .as_mut()
@@ -0,0 +1,120 @@
+//! The block IDs and block tags that the block builtins accept.
+
+use std::collections::HashSet;
+
+use super::Generator;
+
+/// The blocks and block tags of vanilla Minecraft 1.21.
+const VANILLA_BLOCKS: &str = include_str!("blocks.txt");
+
+pub(super) struct BlockRegistry {
+    /// Block IDs like `minecraft:stone`, and block tags like `#minecraft:logs`.
+    entries: HashSet<String>,
+    /// The namespaces with entries. Blocks of other namespaces, e.g. of mods
+    /// or datapacks, are not checked.
+    namespaces: HashSet<String>,
+}
+
+impl BlockRegistry {
+    /// One block ID or `#`-prefixed block tag per line. IDs without a
+    /// namespace are in `minecraft`.
+    pub(super) fn parse(registry: &str) -> Self {
+        let mut entries = HashSet::new();
+        let mut namespaces = HashSet::new();
+        for line in registry
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            let (is_tag, id) = match line.strip_prefix('#') {
+                Some(id) => (true, id),
+                None => (false, line),
+            };
+            let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+            namespaces.insert(namespace.to_owned());
+            entries.insert(format!(
+                "{}{}:{}",
+                if is_tag { "#" } else { "" },
+                namespace,
+                path
+            ));
+        }
+        Self {
+            entries,
+            namespaces,
+        }
+    }
+
+    pub(super) fn vanilla() -> Self {
+        Self::parse(VANILLA_BLOCKS)
+    }
+
+    /// Checks `block`, e.g. `oak_stairs[facing=east]` or
+    /// `chest{Lock:"key"}`, and `#minecraft:logs` if `allow_tags`.
+    pub(super) fn validate(&self, block: &str, allow_tags: bool) -> Result<(), String> {
+        let (is_tag, block) = match block.strip_prefix('#') {
+            Some(block) if allow_tags => (true, block),
+            Some(_) => return Err("block tags are only allowed in `is_block`".into()),
+            None => (false, block),
+        };
+        let id_end = block.find(['[', '{']).unwrap_or(block.len());
+        let (id, rest) = block.split_at(id_end);
+        let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+        let valid_char = |c: char| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.');
+        if namespace.is_empty()
+            || path.is_empty()
+            || !namespace.chars().all(valid_char)
+            || !path.chars().all(|c| valid_char(c) || c == '/')
+        {
+            return Err(format!("invalid block ID \"{}\"", id));
+        }
+        validate_states_and_nbt(rest)?;
+        let full_id = format!("{}{}:{}", if is_tag { "#" } else { "" }, namespace, path);
+        if self.namespaces.contains(namespace) && !self.entries.contains(&full_id) {
+            return Err(format!(
+                "unknown block {}\"{}\"",
+                if is_tag { "tag " } else { "" },
+                full_id
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks the `[states]{nbt}` after a block ID: the brackets and quotes must
+/// be balanced, and there must be no spaces outside of quotes.
+fn validate_states_and_nbt(src: &str) -> Result<(), String> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    for c in src.chars() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '[' | '{' => depth += 1,
+                ']' | '}' if depth == 0 => return Err("unbalanced brackets in block".into()),
+                ']' | '}' => depth -= 1,
+                _ if c.is_whitespace() => {
+                    return Err("blocks cannot contain spaces outside of quotes".into())
+                }
+                _ => {}
+            },
+        }
+    }
+    if depth != 0 || quote.is_some() {
+        return Err("unbalanced brackets in block".into());
+    }
+    Ok(())
+}
+
+impl Generator {
+    /// Replaces the vanilla blocks accepted by the block builtins, see
+    /// `BlockRegistry::parse` for the format.
+    pub fn set_block_registry(&mut self, registry: &str) {
+        self.block_registry = BlockRegistry::parse(registry);
+    }
+}
@@ -0,0 +1,359 @@
+use crate::frontend::ast::{
+    exp::{Exp, ExpType},
+    DataType, Ident, SrcLocation,
+};
+
+use super::{text::text_builtin, ExpVal, Generator, Location, ObjAcc, RegAcc, SemanticError};
+
+/// Where `entity_data_*` and `block_data_*` read and write NBT data.
+enum DataTarget {
+    /// `(selector, path)`
+    Entity,
+    /// `(x, y, z, path)`
+    Block,
+}
+
+/// `entity_data_int`, `set_block_data_array`, ... as the target, whether the
+/// data is written, and whether it is an `int` or an `Array<int>`.
+fn data_builtin(ident: &str) -> Option<(DataTarget, bool, bool)> {
+    let (is_setter, getter) = match ident.strip_prefix("set_") {
+        Some(getter) => (true, getter),
+        None => (false, ident),
+    };
+    let (target, is_array) = match getter {
+        "entity_data_int" => (DataTarget::Entity, false),
+        "entity_data_array" => (DataTarget::Entity, true),
+        "block_data_int" => (DataTarget::Block, false),
+        "block_data_array" => (DataTarget::Block, true),
+        _ => return None,
+    };
+    Some((target, is_setter, is_array))
+}
+
+/// `set_block`, `fill`, `is_block` and `clone` as the number of coordinates
+/// and whether a block comes after them.
+fn block_builtin(ident: &str) -> Option<(usize, bool)> {
+    match ident {
+        "set_block" | "is_block" => Some((3, true)),
+        "fill" => Some((6, true)),
+        "clone" => Some((9, false)),
+        _ => None,
+    }
+}
+
+/// Checks that `path` is a single NBT path, e.g. `Inventory[0].Count`.
+fn validate_nbt_path(path: &str) -> Result<(), &'static str> {
+    if path.is_empty() {
+        return Err("empty NBT path");
+    }
+    let mut depth = 0;
+    let mut quoted = false;
+    for c in path.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => {
+                if depth == 0 {
+                    return Err("unbalanced brackets in NBT path");
+                }
+                depth -= 1;
+            }
+            _ if !quoted && depth == 0 && c.is_whitespace() => {
+                return Err("NBT paths cannot contain spaces")
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 || quoted {
+        return Err("unbalanced brackets in NBT path");
+    }
+    Ok(())
+}
+
+impl Generator {
+    /// Calls the builtin function `ident`, or returns `None` if there is no
+    /// such builtin.
+    pub(super) fn builtin_call(
+        &mut self,
+        ident: &Ident,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Option<Result<ExpVal, SemanticError>> {
+        if let Some((target, is_setter, is_array)) = data_builtin(&ident.string) {
+            return Some(self.data_call(
+                target, is_setter, is_array, arguments, src_loc, reg_acc, obj_acc,
+            ));
+        }
+        if let Some(command) = text_builtin(&ident.string) {
+            return Some(self.text_call(command, arguments, src_loc, reg_acc, obj_acc));
+        }
+        let (coordinate_count, has_block) = block_builtin(&ident.string)?;
+        Some(self.block_call(
+            &ident.string,
+            coordinate_count,
+            has_block,
+            arguments,
+            src_loc,
+            reg_acc,
+            obj_acc,
+        ))
+    }
+
+    /// Compiles `set_block(x, y, z, block)`, `fill(x1, y1, z1, x2, y2, z2,
+    /// block)`, `is_block(x, y, z, block)` and `clone(x1, y1, z1, x2, y2, z2,
+    /// x, y, z)`. The blocks are checked against the block registry.
+    #[allow(clippy::too_many_arguments)]
+    fn block_call(
+        &mut self,
+        ident: &str,
+        coordinate_count: usize,
+        has_block: bool,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        let expected_count = coordinate_count + has_block as usize;
+        if arguments.len() != expected_count {
+            return Err(SemanticError::FuncArgumentsCountMismatch {
+                expected_count,
+                found_count: arguments.len(),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+        let mut macro_arguments = vec![];
+        let mut command_arguments = vec![];
+        for arg in arguments[..coordinate_count].iter_mut() {
+            command_arguments.push(self.int_argument(
+                arg,
+                &mut macro_arguments,
+                reg_acc,
+                obj_acc,
+            )?);
+        }
+        if has_block {
+            let arg = &arguments[coordinate_count];
+            let block = string_argument(arg)?;
+            self.block_registry
+                .validate(&block, ident == "is_block")
+                .map_err(|message| SemanticError::InvalidBlock {
+                    message,
+                    begin: arg.src_loc.begin,
+                    end: arg.src_loc.end,
+                })?;
+            command_arguments.push(block);
+        }
+        let command_arguments = command_arguments.join(" ");
+
+        if ident == "is_block" {
+            let reg = reg_acc.new_reg();
+            self.append_macro_command(
+                &format!(
+                    "execute store success score {} registers if block {}",
+                    reg, command_arguments
+                ),
+                &macro_arguments,
+            );
+            return Ok(ExpVal {
+                data_type: DataType::Int,
+                location: reg,
+            });
+        }
+        let command = match ident {
+            "set_block" => format!("setblock {}", command_arguments),
+            _ => format!("{} {}", ident, command_arguments),
+        };
+        self.append_macro_command(&command, &macro_arguments);
+        Ok(ExpVal {
+            data_type: DataType::Int,
+            location: Location::return_value(),
+        })
+    }
+
+    /// Compiles `entity_data_int(selector, path)`, `block_data_int(x, y, z,
+    /// path)` and the like. Missing data reads as 0 or `[]`.
+    #[allow(clippy::too_many_arguments)]
+    fn data_call(
+        &mut self,
+        target: DataTarget,
+        is_setter: bool,
+        is_array: bool,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        let target_params = match target {
+            DataTarget::Entity => 1,
+            DataTarget::Block => 3,
+        };
+        let expected_count = target_params + 1 + is_setter as usize;
+        if arguments.len() != expected_count {
+            return Err(SemanticError::FuncArgumentsCountMismatch {
+                expected_count,
+                found_count: arguments.len(),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+        let data_type = if is_array {
+            DataType::Array {
+                element_type: Box::new(DataType::Int),
+            }
+        } else {
+            DataType::Int
+        };
+
+        let mut macro_arguments = vec![];
+        let target = match target {
+            DataTarget::Entity => format!(
+                "entity {}",
+                self.selector_argument(&mut arguments[0], &mut macro_arguments, reg_acc, obj_acc)?
+            ),
+            DataTarget::Block => {
+                let mut coordinates = vec![];
+                for arg in arguments[..3].iter_mut() {
+                    coordinates.push(self.int_argument(
+                        arg,
+                        &mut macro_arguments,
+                        reg_acc,
+                        obj_acc,
+                    )?);
+                }
+                format!("block {}", coordinates.join(" "))
+            }
+        };
+        let path = string_argument(&arguments[target_params])?;
+        validate_nbt_path(&path).map_err(|message| SemanticError::InvalidNbtPath {
+            message,
+            begin: arguments[target_params].src_loc.begin,
+            end: arguments[target_params].src_loc.end,
+        })?;
+
+        let temp_data = Location::Memory("memory:temp".into(), "builtin_data".into());
+        if is_setter {
+            let value = &mut arguments[target_params + 1];
+            let value_val = self.eval(value, reg_acc, obj_acc)?;
+            if value_val.data_type != data_type {
+                return Err(SemanticError::TypeMismatch {
+                    expected_type: data_type,
+                    found_type: value_val.data_type,
+                    begin: value.src_loc.begin,
+                    end: value.src_loc.end,
+                });
+            }
+            let command = if is_array {
+                self.mov(&temp_data, &value_val.location);
+                format!(
+                    "data modify {} {} set from storage memory:temp builtin_data",
+                    target, path
+                )
+            } else {
+                let reg = self.to_reg_readonly(&value_val.location, reg_acc);
+                format!(
+                    "execute store result {} {} int 1 run scoreboard players get {} registers",
+                    target, path, reg
+                )
+            };
+            self.append_macro_command(&command, &macro_arguments);
+            return Ok(ExpVal {
+                data_type: DataType::Int,
+                location: Location::return_value(),
+            });
+        }
+
+        if is_array {
+            self.working_mcfunction()
+                .append_command("data modify storage memory:temp builtin_data set value []");
+            self.append_macro_command(
+                &format!(
+                    "data modify storage memory:temp builtin_data set from {} {}",
+                    target, path
+                ),
+                &macro_arguments,
+            );
+            let obj = obj_acc.new_obj(&self.frame);
+            self.mov(&obj, &temp_data);
+            Ok(ExpVal {
+                data_type,
+                location: obj,
+            })
+        } else {
+            let reg = reg_acc.new_reg();
+            self.append_macro_command(
+                &format!(
+                    "execute store result score {} registers run data get {} {}",
+                    reg, target, path
+                ),
+                &macro_arguments,
+            );
+            Ok(ExpVal {
+                data_type,
+                location: reg,
+            })
+        }
+    }
+
+    /// An `int` argument of a builtin as it appears in the command: the
+    /// number if it is known at compile time, a macro argument otherwise.
+    pub(super) fn int_argument(
+        &mut self,
+        arg: &mut Exp,
+        macro_arguments: &mut Vec<Location>,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<String, SemanticError> {
+        if let Some(value) = self.const_eval(arg, &self.scope_namespace)? {
+            return Ok(value.to_string());
+        }
+        let exp_val = self.eval(arg, reg_acc, obj_acc)?;
+        if exp_val.data_type != DataType::Int {
+            return Err(SemanticError::TypeMismatch {
+                expected_type: DataType::Int,
+                found_type: exp_val.data_type,
+                begin: arg.src_loc.begin,
+                end: arg.src_loc.end,
+            });
+        }
+        macro_arguments.push(exp_val.location);
+        Ok(format!("$({})", macro_arguments.len() - 1))
+    }
+
+    /// A `Selector` argument of a builtin as it appears in the command.
+    pub(super) fn selector_argument(
+        &mut self,
+        arg: &mut Exp,
+        macro_arguments: &mut Vec<Location>,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<String, SemanticError> {
+        if let ExpType::Selector(selector) = &arg.exp_type {
+            return Ok(selector.to_owned());
+        }
+        let exp_val = self.eval(arg, reg_acc, obj_acc)?;
+        if exp_val.data_type != DataType::Selector {
+            return Err(SemanticError::TypeMismatch {
+                expected_type: DataType::Selector,
+                found_type: exp_val.data_type,
+                begin: arg.src_loc.begin,
+                end: arg.src_loc.end,
+            });
+        }
+        macro_arguments.push(exp_val.location);
+        Ok(format!("$({})", macro_arguments.len() - 1))
+    }
+}
+
+/// An argument of a builtin that must be a string literal.
+pub(super) fn string_argument(arg: &Exp) -> Result<String, SemanticError> {
+    match &arg.exp_type {
+        ExpType::StringLiteral(string) => Ok(string.to_owned()),
+        _ => Err(SemanticError::ExpectedStringLiteral {
+            begin: arg.src_loc.begin,
+            end: arg.src_loc.end,
+        }),
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::ast::{
+    exp::{ArrayMethodType, Exp, ExpType},
+    Block, BlockItem, CompileUnit, GlobalDef, Stmt,
+};
+
+use super::control_flow;
+
+type FunctionId = (String, String);
+
+/// Stands for the code outside of the MCScript functions, i.e. `extern fn`s,
+/// function tags and `function` commands with placeholders, which may call
+/// any function that can be called from outside.
+pub(super) fn outside() -> FunctionId {
+    (String::new(), "outside".into())
+}
+
+/// Stands for the advancements, whose rewards run in the middle of any
+/// command that grants them, e.g. `inventory_changed` during `give`.
+pub(super) fn advancements() -> FunctionId {
+    (String::new(), "advancements".into())
+}
+
+/// Finds the functions that may be entered again before they return, i.e.
+/// the functions that are part of a cycle in the call graph. Each of them is
+/// mapped to its strongly connected component, so two functions can reach
+/// each other exactly if they are mapped to the same number. `outside()` and
+/// `advancements()` are in the call graph as well: they call the functions
+/// in `external` and `rewards`, except for tasks, which can't be on the
+/// stack.
+pub(super) fn recursive_components(
+    compile_units: &[(CompileUnit, String)],
+    external: &HashSet<FunctionId>,
+    rewards: &HashSet<FunctionId>,
+) -> HashMap<FunctionId, usize> {
+    let mut functions = HashSet::new();
+    let mut tasks = HashSet::new();
+    let mut externs = HashSet::new();
+    for (compile_unit, namespace) in compile_units {
+        for global_def in &compile_unit.global_defs {
+            match global_def {
+                GlobalDef::FuncDef(func_def) => {
+                    let function_id = (namespace.to_owned(), func_def.ident.string.to_owned());
+                    if control_flow::block_may_suspend(&func_def.block) {
+                        tasks.insert(function_id.clone());
+                    }
+                    functions.insert(function_id);
+                }
+                GlobalDef::ExternFuncDef(extern_func_def) => {
+                    externs.insert((
+                        namespace.to_owned(),
+                        extern_func_def.ident.string.to_owned(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut call_graph = HashMap::<FunctionId, HashSet<FunctionId>>::new();
+    for (compile_unit, namespace) in compile_units {
+        for global_def in &compile_unit.global_defs {
+            if let GlobalDef::FuncDef(func_def) = global_def {
+                let mut callees = Callees {
+                    functions: &functions,
+                    found: HashSet::new(),
+                };
+                block_callees(&func_def.block, namespace, &mut callees);
+                let callees = callees
+                    .found
+                    .into_iter()
+                    .map(|callee| {
+                        if externs.contains(&callee) {
+                            outside()
+                        } else {
+                            callee
+                        }
+                    })
+                    .collect();
+                call_graph.insert(
+                    (namespace.to_owned(), func_def.ident.string.to_owned()),
+                    callees,
+                );
+            }
+        }
+    }
+    let mut outside_callees = external.difference(&tasks).cloned().collect::<HashSet<_>>();
+    outside_callees.insert(advancements());
+    call_graph.insert(outside(), outside_callees);
+    call_graph.insert(
+        advancements(),
+        rewards.difference(&tasks).cloned().collect(),
+    );
+
+    let mut tarjan = Tarjan {
+        call_graph: &call_graph,
+        index: HashMap::new(),
+        low_link: HashMap::new(),
+        stack: vec![],
+        on_stack: HashSet::new(),
+        recursive: HashMap::new(),
+    };
+    for function in call_graph.keys() {
+        if !tarjan.index.contains_key(function) {
+            tarjan.visit(function);
+        }
+    }
+    tarjan.recursive
+}
+
+struct Tarjan<'a> {
+    call_graph: &'a HashMap<FunctionId, HashSet<FunctionId>>,
+    index: HashMap<&'a FunctionId, usize>,
+    low_link: HashMap<&'a FunctionId, usize>,
+    stack: Vec<&'a FunctionId>,
+    on_stack: HashSet<&'a FunctionId>,
+    recursive: HashMap<FunctionId, usize>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn visit(&mut self, function: &'a FunctionId) {
+        let index = self.index.len();
+        self.index.insert(function, index);
+        self.low_link.insert(function, index);
+        self.stack.push(function);
+        self.on_stack.insert(function);
+
+        for (callee, _) in self.call_graph[function]
+            .iter()
+            .filter_map(|callee| self.call_graph.get_key_value(callee))
+        {
+            if !self.index.contains_key(callee) {
+                self.visit(callee);
+                let low_link = self.low_link[function].min(self.low_link[callee]);
+                self.low_link.insert(function, low_link);
+            } else if self.on_stack.contains(callee) {
+                let low_link = self.low_link[function].min(self.index[callee]);
+                self.low_link.insert(function, low_link);
+            }
+        }
+
+        if self.low_link[function] == self.index[function] {
+            let mut component = vec![];
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(member);
+                component.push(member);
+                if member == function {
+                    break;
+                }
+            }
+            if component.len() > 1 || self.call_graph[function].contains(function) {
+                self.recursive.extend(
+                    component
+                        .into_iter()
+                        .map(|member| (member.to_owned(), index)),
+                );
+            }
+        }
+    }
+}
+
+/// The functions a `run_command!` may call: the targets of `function`
+/// commands that are MCScript functions according to `is_function`, and
+/// `outside()` for the others, function tags and targets with placeholders.
+/// Any command may also grant advancements.
+pub(super) fn command_callees(
+    fmt_str: &str,
+    is_function: impl Fn(&FunctionId) -> bool,
+) -> Vec<FunctionId> {
+    let mut callees = vec![advancements()];
+    let mut tokens = fmt_str.split(|c: char| c.is_whitespace() || c == '"');
+    while let Some(token) = tokens.next() {
+        if token.trim_start_matches('/') != "function" {
+            continue;
+        }
+        let Some(target) = tokens.next() else {
+            break;
+        };
+        let callee = target
+            .split_once(':')
+            .filter(|_| !target.contains(['#', '{', '$']))
+            .map(|(namespace, path)| {
+                let ident = path.split('-').next().unwrap();
+                (namespace.to_owned(), ident.to_owned())
+            })
+            .filter(&is_function);
+        callees.push(callee.unwrap_or_else(outside));
+    }
+    callees
+}
+
+/// The callees found in a function so far.
+struct Callees<'a> {
+    /// The MCScript functions, but not the `extern fn`s.
+    functions: &'a HashSet<FunctionId>,
+    found: HashSet<FunctionId>,
+}
+
+fn block_callees(block: &Block, namespace: &str, callees: &mut Callees) {
+    for block_item in &block.0 {
+        match block_item {
+            BlockItem::Decl(decl) => exp_callees(&decl.init_value, namespace, callees),
+            BlockItem::Stmt(stmt) => stmt_callees(stmt, namespace, callees),
+        }
+    }
+}
+
+fn stmt_callees(stmt: &Stmt, namespace: &str, callees: &mut Callees) {
+    match stmt {
+        Stmt::Return { return_value, .. } => {
+            if let Some(return_value) = return_value {
+                exp_callees(return_value, namespace, callees);
+            }
+        }
+        Stmt::Assign { lhs, new_value } => {
+            exp_callees(lhs, namespace, callees);
+            exp_callees(new_value, namespace, callees);
+        }
+        Stmt::Block(block) => block_callees(block, namespace, callees),
+        Stmt::IfElse {
+            exp,
+            if_branch,
+            else_branch,
+        } => {
+            exp_callees(exp, namespace, callees);
+            block_callees(if_branch, namespace, callees);
+            if let Some(else_branch) = else_branch {
+                block_callees(else_branch, namespace, callees);
+            }
+        }
+        Stmt::While { exp, body } => {
+            exp_callees(exp, namespace, callees);
+            block_callees(body, namespace, callees);
+        }
+        Stmt::Execute {
+            modifiers, body, ..
+        } => {
+            for modifier in modifiers {
+                exp_callees(&modifier.argument, namespace, callees);
+            }
+            block_callees(body, namespace, callees);
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Yield { .. } => {}
+        Stmt::Sleep { ticks, .. } => exp_callees(ticks, namespace, callees),
+        Stmt::Exp(exp) => exp_callees(exp, namespace, callees),
+        Stmt::InlineCommand {
+            fmt_str, arguments, ..
+        } => {
+            let functions = callees.functions;
+            callees.found.extend(command_callees(fmt_str, |callee| {
+                functions.contains(callee)
+            }));
+            for arg in arguments {
+                exp_callees(arg, namespace, callees);
+            }
+        }
+    }
+}
+
+fn exp_callees(exp: &Exp, namespace: &str, callees: &mut Callees) {
+    match &exp.exp_type {
+        ExpType::Number(_)
+        | ExpType::Variable { .. }
+        | ExpType::Running { .. }
+        | ExpType::StringLiteral(_)
+        | ExpType::Selector(_) => {}
+        ExpType::SelectorWith {
+            selector,
+            arguments,
+            ..
+        } => {
+            exp_callees(selector, namespace, callees);
+            for arg in arguments {
+                exp_callees(arg, namespace, callees);
+            }
+        }
+        ExpType::UnaryExp(_, exp) => exp_callees(exp, namespace, callees),
+        ExpType::BinaryExp(_, lhs, rhs) => {
+            exp_callees(lhs, namespace, callees);
+            exp_callees(rhs, namespace, callees);
+        }
+        ExpType::ArrayElement { array, subscript } => {
+            exp_callees(array, namespace, callees);
+            exp_callees(subscript, namespace, callees);
+        }
+        ExpType::ArrayMethod { array, method } => {
+            exp_callees(array, namespace, callees);
+            match method {
+                ArrayMethodType::Size | ArrayMethodType::Pop => {}
+                ArrayMethodType::Push { value } => exp_callees(value, namespace, callees),
+                ArrayMethodType::Insert { pos, value } => {
+                    exp_callees(pos, namespace, callees);
+                    exp_callees(value, namespace, callees);
+                }
+                ArrayMethodType::Erase { pos } => exp_callees(pos, namespace, callees),
+            }
+        }
+        ExpType::FuncCall {
+            namespace: callee_namespace,
+            func_ident,
+            arguments,
+        } => {
+            let callee_namespace = match callee_namespace {
+                Some(callee_namespace) => callee_namespace.string.to_owned(),
+                None => namespace.to_owned(),
+            };
+            callees
+                .found
+                .insert((callee_namespace, func_ident.string.to_owned()));
+            for arg in arguments {
+                exp_callees(arg, namespace, callees);
+            }
+        }
+        ExpType::NewArray { length, element } => {
+            exp_callees(length, namespace, callees);
+            exp_callees(element, namespace, callees);
+        }
+        ExpType::SquareBracketsArray { elements, .. } => {
+            for element in elements {
+                exp_callees(element, namespace, callees);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+
+use crate::frontend::ast::SrcLocation;
+
+use super::{Generator, Location, ObjAcc, RegAcc};
+
+/// A source file, for telling the player where a runtime check failed.
+pub struct SourceFile {
+    path: String,
+    line_starts: Vec<usize>,
+}
+
+impl SourceFile {
+    pub fn new(path: &str, content: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            // the path ends up in a quoted string of a macro argument
+            path: path.replace('\\', "/").replace('"', ""),
+            line_starts,
+        }
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        self.line_starts.partition_point(|start| *start <= offset)
+    }
+}
+
+impl Generator {
+    /// Makes the generated code check array subscripts and divisors at
+    /// runtime. The sources are keyed by namespace.
+    pub fn enable_checks(&mut self, source_files: HashMap<String, SourceFile>) {
+        self.source_files = Some(source_files);
+    }
+
+    /// After a call, leaves the label if the callee failed a check. The
+    /// callers do the same, so each of them pops its frame on the way out.
+    pub(super) fn append_abort_check(&mut self) {
+        if self.source_files.is_some() {
+            self.working_mcfunction()
+                .append_command("execute if score abort registers matches 1 run return 0");
+        }
+    }
+
+    /// Aborts unless `0 <= index < array.size()`, or `<=` if `allow_end`.
+    pub(super) fn check_index(
+        &mut self,
+        array: &Location,
+        index: &Location,
+        allow_end: bool,
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) {
+        if self.source_files.is_none() {
+            return;
+        }
+        let size = self.load_array_size(array, reg_acc, obj_acc);
+        let index = self.to_reg_readonly(index, reg_acc);
+        let abort = self.abort_command("array index out of bounds", src_loc);
+        self.working_mcfunction().append_commands(vec![
+            &format!(
+                "execute unless score {} registers matches 0.. run {}",
+                index, abort
+            ),
+            &format!(
+                "execute if score {} registers {} {} registers run {}",
+                index,
+                if allow_end { ">" } else { ">=" },
+                size,
+                abort
+            ),
+        ]);
+    }
+
+    /// Aborts if `array` is empty.
+    pub(super) fn check_not_empty(
+        &mut self,
+        array: &Location,
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) {
+        if self.source_files.is_none() {
+            return;
+        }
+        let size = self.load_array_size(array, reg_acc, obj_acc);
+        let abort = self.abort_command("pop from an empty array", src_loc);
+        self.working_mcfunction().append_command(&format!(
+            "execute if score {} registers matches ..0 run {}",
+            size, abort
+        ));
+    }
+
+    /// Aborts if the divisor of `/` or `%` is zero.
+    pub(super) fn check_divisor(&mut self, divisor: &Location, src_loc: &SrcLocation) {
+        if self.source_files.is_none() {
+            return;
+        }
+        let abort = self.abort_command("division by zero", src_loc);
+        self.working_mcfunction().append_command(&format!(
+            "execute if score {} registers matches 0 run {}",
+            divisor, abort
+        ));
+    }
+
+    fn abort_command(&self, message: &str, src_loc: &SrcLocation) -> String {
+        let source_file = &self.source_files.as_ref().unwrap()[&self.scope_namespace];
+        format!(
+            "return run function mcscript:abort {{message: \"{}:{}: {}\"}}",
+            source_file.path,
+            source_file.line(src_loc.begin),
+            message
+        )
+    }
+}
@@ -0,0 +1,79 @@
+use crate::backend::command_tree::CommandTree;
+
+use super::{Generator, SemanticError};
+
+/// How many commands the mcfunctions of the working namespace had, so that
+/// the commands generated afterwards can be checked.
+pub(super) struct CommandSnapshot {
+    lens: Vec<usize>,
+    working_mcfunction: Option<(String, usize)>,
+}
+
+impl Generator {
+    /// Checks the generated commands against `command_tree` from now on.
+    pub fn set_command_tree(&mut self, command_tree: CommandTree) {
+        self.command_tree = Some(command_tree);
+    }
+
+    /// `None` if the commands are not checked.
+    pub(super) fn command_snapshot(&self) -> Option<CommandSnapshot> {
+        self.command_tree.as_ref()?;
+        Some(CommandSnapshot {
+            lens: self
+                .working_namespace
+                .as_ref()
+                .unwrap()
+                .mcfunctions()
+                .iter()
+                .map(|mcfunction| mcfunction.commands().len())
+                .collect(),
+            working_mcfunction: self
+                .working_mcfunction
+                .as_ref()
+                .map(|mcfunction| (mcfunction.name().to_owned(), mcfunction.commands().len())),
+        })
+    }
+
+    /// Checks the commands generated since `snapshot` was taken, and reports
+    /// the invalid ones at `begin..end`. The commands of `run_mod_command!`
+    /// are not checked.
+    pub(super) fn validate_commands_since(
+        &self,
+        snapshot: Option<CommandSnapshot>,
+        begin: usize,
+        end: usize,
+    ) -> Result<(), SemanticError> {
+        let (Some(snapshot), Some(command_tree)) = (snapshot, &self.command_tree) else {
+            return Ok(());
+        };
+        let namespace = self.working_namespace.as_ref().unwrap();
+        let mut commands = vec![];
+        for (i, mcfunction) in namespace.mcfunctions().iter().enumerate() {
+            let id = format!("{}:{}", namespace.name(), mcfunction.name());
+            if !self.mod_commands.contains(&id) {
+                let from = snapshot.lens.get(i).copied().unwrap_or(0);
+                commands.extend(&mcfunction.commands()[from..]);
+            }
+        }
+        if let Some(mcfunction) = &self.working_mcfunction {
+            let from = match &snapshot.working_mcfunction {
+                Some((name, len)) if name == mcfunction.name() => *len,
+                _ => 0,
+            };
+            commands.extend(&mcfunction.commands()[from..]);
+        }
+        for command in commands {
+            if command.is_empty() || command.starts_with('#') {
+                continue;
+            }
+            command_tree
+                .validate(command)
+                .map_err(|message| SemanticError::InvalidCommand {
+                    message,
+                    begin,
+                    end,
+                })?;
+        }
+        Ok(())
+    }
+}
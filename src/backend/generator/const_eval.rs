@@ -0,0 +1,143 @@
+use crate::frontend::ast::{
+    exp::{BinaryOp, Exp, ExpType, UnaryOp},
+    CompileUnit, GlobalDef,
+};
+
+use super::{Generator, SemanticError};
+
+impl Generator {
+    /// Defines all the `const` globals. A constant may refer to constants
+    /// defined later or in other namespaces, so the definitions are folded
+    /// repeatedly until none of the remaining ones can be resolved.
+    pub(super) fn resolve_constants(
+        &mut self,
+        compile_units: &[(CompileUnit, String)],
+    ) -> Result<(), (String, SemanticError)> {
+        let mut pending = vec![];
+        for (compile_unit, namespace) in compile_units {
+            for global_def in &compile_unit.global_defs {
+                if let GlobalDef::ConstDef { ident, value } = global_def {
+                    pending.push((namespace, ident, value));
+                }
+            }
+        }
+        while !pending.is_empty() {
+            let mut unresolved = vec![];
+            let mut first_err = None;
+            for (namespace, ident, value) in pending.iter() {
+                match self.const_eval(value, namespace) {
+                    Ok(Some(result)) => self
+                        .variable_table
+                        .new_constant(ident, namespace, result)
+                        .map_err(|err| (namespace.to_string(), err))?,
+                    Ok(None) => {
+                        return Err((
+                            namespace.to_string(),
+                            SemanticError::NonConstantExpression {
+                                begin: value.src_loc.begin,
+                                end: value.src_loc.end,
+                            },
+                        ))
+                    }
+                    Err(err) => {
+                        first_err.get_or_insert((namespace.to_string(), err));
+                        unresolved.push((*namespace, *ident, *value));
+                    }
+                }
+            }
+            if unresolved.len() == pending.len() {
+                return Err(first_err.unwrap());
+            }
+            pending = unresolved;
+        }
+        Ok(())
+    }
+
+    /// Evaluates `exp` at compile time. Returns `Ok(None)` if `exp` is not a
+    /// constant expression of type `int`.
+    pub(super) fn const_eval(
+        &self,
+        exp: &Exp,
+        namespace: &str,
+    ) -> Result<Option<i32>, SemanticError> {
+        match &exp.exp_type {
+            ExpType::Number(num) => Ok(Some(*num)),
+            ExpType::Variable {
+                ident,
+                namespace: prefix,
+            } => {
+                let variable = self.variable_table.query_variable(
+                    ident,
+                    &prefix.as_ref().map(|n| n.string.to_owned()),
+                    namespace,
+                )?;
+                Ok(variable.const_value)
+            }
+            ExpType::UnaryExp(op, exp) => Ok(self
+                .const_eval(exp, namespace)?
+                .map(|value| fold_unary(op, value))),
+            ExpType::BinaryExp(op, lhs, rhs_exp) => {
+                let lhs = self.const_eval(lhs, namespace)?;
+                let rhs = self.const_eval(rhs_exp, namespace)?;
+                if matches!(op, BinaryOp::Div | BinaryOp::Mod) && rhs == Some(0) {
+                    return Err(SemanticError::DivisionByZero {
+                        begin: rhs_exp.src_loc.begin,
+                        end: rhs_exp.src_loc.end,
+                    });
+                }
+                match (lhs, rhs) {
+                    (Some(lhs), Some(rhs)) => Ok(Some(fold_binary(op, lhs, rhs))),
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+fn fold_unary(op: &UnaryOp, value: i32) -> i32 {
+    match op {
+        UnaryOp::Positive => value,
+        UnaryOp::Negative => value.wrapping_neg(),
+        UnaryOp::Not => (value == 0) as i32,
+    }
+}
+
+/// Folds a binary operation the same way the scoreboard would compute it.
+/// The divisor is never zero.
+fn fold_binary(op: &BinaryOp, lhs: i32, rhs: i32) -> i32 {
+    match op {
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div => floor_div(lhs, rhs),
+        BinaryOp::Mod => floor_mod(lhs, rhs),
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Ne => (lhs != rhs) as i32,
+        BinaryOp::LAnd => (lhs != 0 && rhs != 0) as i32,
+        BinaryOp::LOr => (lhs != 0 || rhs != 0) as i32,
+    }
+}
+
+// `scoreboard players operation` uses Java's `Math.floorDiv` and `Math.floorMod`.
+fn floor_div(lhs: i32, rhs: i32) -> i32 {
+    let quotient = lhs.wrapping_div(rhs);
+    if lhs.wrapping_rem(rhs) != 0 && (lhs < 0) != (rhs < 0) {
+        quotient - 1
+    } else {
+        quotient
+    }
+}
+
+fn floor_mod(lhs: i32, rhs: i32) -> i32 {
+    let remainder = lhs.wrapping_rem(rhs);
+    if remainder != 0 && (remainder < 0) != (rhs < 0) {
+        remainder + rhs
+    } else {
+        remainder
+    }
+}
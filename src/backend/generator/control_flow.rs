@@ -0,0 +1,161 @@
+use crate::frontend::ast::{
+    exp::{ArrayMethodType, Exp, ExpType},
+    Block, BlockItem, Stmt,
+};
+
+/// Whether control never reaches the end of `block`, because it always
+/// leaves through `return`, `break` or `continue`.
+pub(super) fn block_diverges(block: &Block) -> bool {
+    block.0.iter().any(|block_item| match block_item {
+        BlockItem::Decl(_) => false,
+        BlockItem::Stmt(stmt) => stmt_diverges(stmt),
+    })
+}
+
+pub(super) fn stmt_diverges(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Return { .. } | Stmt::Break { .. } | Stmt::Continue { .. } => true,
+        Stmt::Block(block) => block_diverges(block),
+        Stmt::IfElse {
+            if_branch,
+            else_branch,
+            ..
+        } => block_diverges(if_branch) && else_branch.as_ref().is_some_and(block_diverges),
+        Stmt::While { .. }
+        | Stmt::Execute { .. }
+        | Stmt::Assign { .. }
+        | Stmt::Exp(_)
+        | Stmt::InlineCommand { .. }
+        | Stmt::Yield { .. }
+        | Stmt::Sleep { .. } => false,
+    }
+}
+
+/// Whether some `return` appears anywhere in `block`.
+pub(super) fn block_contains_return(block: &Block) -> bool {
+    block.0.iter().any(|block_item| match block_item {
+        BlockItem::Stmt(Stmt::Return { .. }) => true,
+        BlockItem::Stmt(Stmt::Block(block)) | BlockItem::Stmt(Stmt::While { body: block, .. }) => {
+            block_contains_return(block)
+        }
+        BlockItem::Stmt(Stmt::IfElse {
+            if_branch,
+            else_branch,
+            ..
+        }) => {
+            block_contains_return(if_branch)
+                || else_branch.as_ref().is_some_and(block_contains_return)
+        }
+        _ => false,
+    })
+}
+
+/// Whether `block` contains `yield` or `sleep`, i.e. its function is a task.
+pub(super) fn block_may_suspend(block: &Block) -> bool {
+    block.0.iter().any(|block_item| match block_item {
+        BlockItem::Stmt(Stmt::Yield { .. }) | BlockItem::Stmt(Stmt::Sleep { .. }) => true,
+        BlockItem::Stmt(Stmt::Block(block)) | BlockItem::Stmt(Stmt::While { body: block, .. }) => {
+            block_may_suspend(block)
+        }
+        BlockItem::Stmt(Stmt::IfElse {
+            if_branch,
+            else_branch,
+            ..
+        }) => block_may_suspend(if_branch) || else_branch.as_ref().is_some_and(block_may_suspend),
+        _ => false,
+    })
+}
+
+/// Whether running `block` may start another loop, which overwrites
+/// `loop_state`. Calls are assumed to do so.
+pub(super) fn block_may_run_loop(block: &Block) -> bool {
+    block.0.iter().any(|block_item| match block_item {
+        BlockItem::Decl(decl) => exp_may_run_loop(&decl.init_value),
+        BlockItem::Stmt(stmt) => match stmt {
+            Stmt::Return { return_value, .. } => return_value
+                .as_ref()
+                .is_some_and(|exp| exp_may_run_loop(exp)),
+            Stmt::Assign { lhs, new_value } => exp_may_run_loop(lhs) || exp_may_run_loop(new_value),
+            Stmt::Block(block) => block_may_run_loop(block),
+            Stmt::IfElse {
+                exp,
+                if_branch,
+                else_branch,
+            } => {
+                exp_may_run_loop(exp)
+                    || block_may_run_loop(if_branch)
+                    || else_branch.as_ref().is_some_and(block_may_run_loop)
+            }
+            Stmt::While { .. } => true,
+            Stmt::Execute {
+                modifiers, body, ..
+            } => {
+                modifiers
+                    .iter()
+                    .any(|modifier| exp_may_run_loop(&modifier.argument))
+                    || block_may_run_loop(body)
+            }
+            Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Yield { .. } => false,
+            Stmt::Sleep { ticks, .. } => exp_may_run_loop(ticks),
+            Stmt::Exp(exp) => exp_may_run_loop(exp),
+            // any command may call functions, e.g. the rewards of advancements
+            Stmt::InlineCommand { .. } => true,
+        },
+    })
+}
+
+pub(super) fn exp_may_run_loop(exp: &Exp) -> bool {
+    match &exp.exp_type {
+        ExpType::Number(_)
+        | ExpType::Variable { .. }
+        | ExpType::Running { .. }
+        | ExpType::StringLiteral(_)
+        | ExpType::Selector(_) => false,
+        ExpType::SelectorWith {
+            selector,
+            arguments,
+            ..
+        } => exp_may_run_loop(selector) || arguments.iter().any(|arg| exp_may_run_loop(arg)),
+        ExpType::FuncCall { .. } | ExpType::NewArray { .. } => true,
+        ExpType::UnaryExp(_, exp) => exp_may_run_loop(exp),
+        ExpType::BinaryExp(_, lhs, rhs) => exp_may_run_loop(lhs) || exp_may_run_loop(rhs),
+        ExpType::ArrayElement { array, subscript } => {
+            exp_may_run_loop(array) || exp_may_run_loop(subscript)
+        }
+        ExpType::ArrayMethod { array, method } => {
+            exp_may_run_loop(array)
+                || match method {
+                    ArrayMethodType::Size | ArrayMethodType::Pop => false,
+                    ArrayMethodType::Push { value } => exp_may_run_loop(value),
+                    ArrayMethodType::Insert { pos, value } => {
+                        exp_may_run_loop(pos) || exp_may_run_loop(value)
+                    }
+                    ArrayMethodType::Erase { pos } => exp_may_run_loop(pos),
+                }
+        }
+        ExpType::SquareBracketsArray { elements, .. } => {
+            elements.iter().any(|element| exp_may_run_loop(element))
+        }
+    }
+}
+
+/// The source range to point at when reporting `block_item`.
+pub(super) fn block_item_span(block_item: &BlockItem) -> Option<(usize, usize)> {
+    match block_item {
+        BlockItem::Decl(decl) => Some((decl.ident.src_loc.begin, decl.init_value.src_loc.end)),
+        BlockItem::Stmt(stmt) => match stmt {
+            Stmt::Return { src_loc, .. }
+            | Stmt::Break { src_loc }
+            | Stmt::Continue { src_loc }
+            | Stmt::InlineCommand { src_loc, .. }
+            | Stmt::Yield { src_loc }
+            | Stmt::Sleep { src_loc, .. }
+            | Stmt::Execute { src_loc, .. } => Some((src_loc.begin, src_loc.end)),
+            Stmt::Assign { lhs, new_value } => Some((lhs.src_loc.begin, new_value.src_loc.end)),
+            Stmt::Block(block) => block.0.first().and_then(block_item_span),
+            Stmt::IfElse { exp, .. } | Stmt::While { exp, .. } | Stmt::Exp(exp) => {
+                Some((exp.src_loc.begin, exp.src_loc.end))
+            }
+        },
+    }
+}
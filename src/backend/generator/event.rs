@@ -0,0 +1,418 @@
+//! Functions run when something happens to a player, declared with `#[on]`,
+//! `#[on_advancement]` and `#[on_score]`.
+
+use serde_json::{json, Value};
+
+use crate::{
+    backend::datapack::{Mcfunction, Resource},
+    frontend::ast::{exp::ExpType, Attribute, FuncDef},
+};
+
+use super::{Generator, SemanticError};
+
+/// The advancement triggers of vanilla Minecraft 1.21.
+const TRIGGERS: [&str; 56] = [
+    "allay_drop_item_on_block",
+    "any_block_use",
+    "avoid_vibration",
+    "bee_nest_destroyed",
+    "bred_animals",
+    "brewed_potion",
+    "changed_dimension",
+    "channeled_lightning",
+    "construct_beacon",
+    "consume_item",
+    "crafter_recipe_crafted",
+    "cured_zombie_villager",
+    "default_block_use",
+    "effects_changed",
+    "enchanted_item",
+    "enter_block",
+    "entity_hurt_player",
+    "entity_killed_player",
+    "fall_after_explosion",
+    "fall_from_height",
+    "filled_bucket",
+    "fishing_rod_hooked",
+    "hero_of_the_village",
+    "impossible",
+    "inventory_changed",
+    "item_durability_changed",
+    "item_used_on_block",
+    "kill_mob_near_sculk_catalyst",
+    "killed_by_arrow",
+    "levitation",
+    "lightning_strike",
+    "location",
+    "nether_travel",
+    "placed_block",
+    "player_generates_container_loot",
+    "player_hurt_entity",
+    "player_interacted_with_entity",
+    "player_killed_entity",
+    "recipe_crafted",
+    "recipe_unlocked",
+    "ride_entity_in_lava",
+    "shot_crossbow",
+    "slept_in_bed",
+    "slide_down_block",
+    "started_riding",
+    "summoned_entity",
+    "tame_animal",
+    "target_hit",
+    "thrown_item_picked_up_by_entity",
+    "thrown_item_picked_up_by_player",
+    "tick",
+    "used_ender_eye",
+    "used_totem",
+    "using_item",
+    "villager_trade",
+    "voluntary_exile",
+];
+
+/// The function that runs the `#[on_score]` handlers every tick. It is not
+/// in `on/`, so it can't have the name of a handler.
+const DISPATCHER: &str = "on-tick";
+
+/// The criterion of the objective behind `#[on(player_joined)]`.
+const LEAVE_GAME: &str = "minecraft.custom:minecraft.leave_game";
+
+pub(super) enum Event {
+    /// `#[on(trigger, "conditions")]`, a generated advancement.
+    Trigger {
+        trigger: String,
+        conditions: Option<Value>,
+    },
+    /// `#[on_advancement("name")]`, an advancement declared with `resource`.
+    Advancement(String),
+    /// `#[on_score("criterion")]`, a score that becomes positive. It is also
+    /// used by `#[on(player_joined)]`, which handles the first join with a
+    /// tag.
+    Score { criterion: String, first_join: bool },
+}
+
+pub(super) struct EventHandler {
+    namespace: String,
+    func_ident: String,
+    /// The name of the reward function, e.g. `on/f`, which is also the name
+    /// of the generated advancement.
+    name: String,
+    event: Event,
+    begin: usize,
+    end: usize,
+}
+
+impl EventHandler {
+    fn objective(&self) -> String {
+        format!("{}.{}", self.namespace, self.name.replace('/', "."))
+    }
+}
+
+/// The `i`th argument of `attribute`, an identifier if `ident` and a string
+/// literal otherwise.
+fn attribute_argument(
+    attribute: &Attribute,
+    i: usize,
+    ident: bool,
+) -> Result<String, SemanticError> {
+    let argument = &attribute.arguments[i];
+    match &argument.exp_type {
+        ExpType::Variable {
+            ident: variable,
+            namespace: None,
+        } if ident => Ok(variable.string.to_owned()),
+        ExpType::StringLiteral(string) if !ident => Ok(string.to_owned()),
+        _ => Err(SemanticError::InvalidEventHandler {
+            message: if ident {
+                "expected an advancement trigger, e.g. `player_killed_entity`".into()
+            } else {
+                "expected a string literal".into()
+            },
+            begin: argument.src_loc.begin,
+            end: argument.src_loc.end,
+        }),
+    }
+}
+
+impl Generator {
+    /// Records the handler declared by `attribute`, which is `on`,
+    /// `on_advancement` or `on_score`.
+    pub(super) fn scan_event_handler(
+        &mut self,
+        namespace: &str,
+        func_def: &FuncDef,
+        attribute: &Attribute,
+    ) -> Result<(), SemanticError> {
+        let attribute_ident = attribute.ident.string.as_str();
+        let error = |message: String| SemanticError::InvalidEventHandler {
+            message,
+            begin: attribute.ident.src_loc.begin,
+            end: attribute.ident.src_loc.end,
+        };
+        let max_count = if attribute_ident == "on" { 2 } else { 1 };
+        if attribute.arguments.is_empty() || attribute.arguments.len() > max_count {
+            return Err(error(format!(
+                "\"#[{}]\" expects {} argument(s), found {}",
+                attribute_ident,
+                if max_count == 2 { "1 or 2" } else { "1" },
+                attribute.arguments.len()
+            )));
+        }
+        if !func_def.params.is_empty() {
+            return Err(SemanticError::TaggedFunctionWithParameters {
+                attribute: attribute_ident.to_owned(),
+                begin: attribute.ident.src_loc.begin,
+                end: attribute.ident.src_loc.end,
+            });
+        }
+
+        let event = match attribute_ident {
+            "on" => {
+                let trigger = attribute_argument(attribute, 0, true)?;
+                let trigger_src_loc = &attribute.arguments[0].src_loc;
+                let conditions = match attribute.arguments.get(1) {
+                    Some(argument) => {
+                        let conditions = serde_json::from_str::<Value>(&attribute_argument(
+                            attribute, 1, false,
+                        )?)
+                        .map_err(|err| format!("invalid JSON: {}", err))
+                        .and_then(|conditions| match conditions.is_object() {
+                            true => Ok(conditions),
+                            false => Err("the conditions must be a JSON object".into()),
+                        })
+                        .map_err(|message| {
+                            SemanticError::InvalidEventHandler {
+                                message,
+                                begin: argument.src_loc.begin,
+                                end: argument.src_loc.end,
+                            }
+                        })?;
+                        Some(conditions)
+                    }
+                    None => None,
+                };
+                if trigger == "player_joined" {
+                    if conditions.is_some() {
+                        return Err(error("\"player_joined\" takes no conditions".into()));
+                    }
+                    Event::Score {
+                        criterion: LEAVE_GAME.into(),
+                        first_join: true,
+                    }
+                } else if TRIGGERS.contains(&trigger.as_str()) {
+                    Event::Trigger {
+                        trigger,
+                        conditions,
+                    }
+                } else {
+                    return Err(SemanticError::InvalidEventHandler {
+                        message: format!(
+                            "unknown advancement trigger \"{}\", expected one of the triggers of Minecraft or `player_joined`",
+                            trigger
+                        ),
+                        begin: trigger_src_loc.begin,
+                        end: trigger_src_loc.end,
+                    });
+                }
+            }
+            "on_advancement" => Event::Advancement(attribute_argument(attribute, 0, false)?),
+            _ => {
+                let criterion = attribute_argument(attribute, 0, false)?;
+                if criterion.is_empty() || criterion.contains(char::is_whitespace) {
+                    let src_loc = &attribute.arguments[0].src_loc;
+                    return Err(SemanticError::InvalidEventHandler {
+                        message: format!("invalid scoreboard criterion \"{}\"", criterion),
+                        begin: src_loc.begin,
+                        end: src_loc.end,
+                    });
+                }
+                Event::Score {
+                    criterion,
+                    first_join: false,
+                }
+            }
+        };
+
+        let func_ident = func_def.ident.string.to_owned();
+        let count = self
+            .event_handlers
+            .iter()
+            .filter(|handler| handler.namespace == namespace && handler.func_ident == func_ident)
+            .count();
+        let name = match count {
+            0 => format!("on/{}", func_ident),
+            _ => format!("on/{}-{}", func_ident, count + 1),
+        };
+        self.entry_points.push(format!("{}:{}", namespace, name));
+        self.external_functions
+            .insert((namespace.to_owned(), func_ident.to_owned()));
+        if !matches!(event, Event::Score { .. }) {
+            self.reward_functions
+                .insert((namespace.to_owned(), func_ident.to_owned()));
+        }
+        self.event_handlers.push(EventHandler {
+            namespace: namespace.to_owned(),
+            func_ident,
+            name,
+            event,
+            begin: attribute.ident.src_loc.begin,
+            end: attribute.ident.src_loc.end,
+        });
+        Ok(())
+    }
+
+    /// Adds the advancements of `#[on]`, and sets the rewards of the ones of
+    /// `#[on_advancement]`.
+    pub(super) fn resolve_event_handlers(&mut self) -> Result<(), (String, SemanticError)> {
+        let mut tick_namespaces = vec![];
+        for handler in &self.event_handlers {
+            let reward = format!("{}:{}", handler.namespace, handler.name);
+            let error = |message: String| {
+                (
+                    handler.namespace.to_owned(),
+                    SemanticError::InvalidEventHandler {
+                        message,
+                        begin: handler.begin,
+                        end: handler.end,
+                    },
+                )
+            };
+            match &handler.event {
+                Event::Trigger {
+                    trigger,
+                    conditions,
+                } => {
+                    let mut criterion = json!({ "trigger": format!("minecraft:{}", trigger) });
+                    if let Some(conditions) = conditions {
+                        criterion["conditions"] = conditions.clone();
+                    }
+                    let advancement = json!({
+                        "criteria": { "event": criterion },
+                        "rewards": { "function": reward },
+                    });
+                    self.resources
+                        .entry(handler.namespace.to_owned())
+                        .or_default()
+                        .push(Resource::new(
+                            "advancement".into(),
+                            handler.name.to_owned(),
+                            serde_json::to_string_pretty(&advancement).unwrap(),
+                        ));
+                }
+                Event::Advancement(name) => {
+                    let resource = self
+                        .resources
+                        .get_mut(&handler.namespace)
+                        .and_then(|resources| {
+                            resources.iter_mut().find(|resource| {
+                                resource.kind() == "advancement" && resource.name() == name
+                            })
+                        })
+                        .ok_or_else(|| {
+                            error(format!(
+                                "no advancement \"{}\" is declared with `resource` in this namespace",
+                                name
+                            ))
+                        })?;
+                    let mut advancement: Value = serde_json::from_str(resource.json()).unwrap();
+                    if advancement
+                        .get("rewards")
+                        .and_then(|rewards| rewards.get("function"))
+                        .is_some()
+                    {
+                        return Err(error(format!(
+                            "advancement \"{}\" already has a reward function",
+                            name
+                        )));
+                    }
+                    match advancement.get_mut("rewards") {
+                        Some(Value::Object(rewards)) => {
+                            rewards.insert("function".into(), Value::String(reward));
+                        }
+                        _ => advancement["rewards"] = json!({ "function": reward }),
+                    }
+                    *resource.json_mut() = serde_json::to_string_pretty(&advancement).unwrap();
+                }
+                Event::Score { .. } => {
+                    if !tick_namespaces.contains(&handler.namespace) {
+                        tick_namespaces.push(handler.namespace.to_owned());
+                    }
+                }
+            }
+        }
+        for namespace in tick_namespaces {
+            self.datapack
+                .append_to_function_tag("minecraft:tick", format!("{}:{}", namespace, DISPATCHER));
+        }
+        Ok(())
+    }
+
+    /// Creates the objectives of the `#[on_score]` handlers of the working
+    /// namespace, in `init`.
+    pub(super) fn append_event_objectives(&mut self) {
+        let namespace = self.working_namespace_name().to_owned();
+        let mut commands = vec![];
+        for handler in &self.event_handlers {
+            if let (true, Event::Score { criterion, .. }) =
+                (handler.namespace == namespace, &handler.event)
+            {
+                commands.push(format!(
+                    "scoreboard objectives add {} {}",
+                    handler.objective(),
+                    criterion
+                ));
+            }
+        }
+        for command in commands {
+            self.working_mcfunction().append_command(&command);
+        }
+    }
+
+    /// Generates the reward functions of the handlers of the working
+    /// namespace, which run as the player, and the dispatcher, which runs the
+    /// ones of `#[on_score]`.
+    pub(super) fn append_event_handlers(&mut self) {
+        let namespace = self.working_namespace_name().to_owned();
+        let mut mcfunctions = vec![];
+        let mut tick = Mcfunction::new(DISPATCHER.into());
+        for handler in &self.event_handlers {
+            if handler.namespace != namespace {
+                continue;
+            }
+            let mut reward = Mcfunction::new(handler.name.to_owned());
+            match &handler.event {
+                Event::Trigger { .. } => reward.append_command(&format!(
+                    "advancement revoke @s only {}:{}",
+                    namespace, handler.name
+                )),
+                Event::Advancement(name) => reward.append_command(&format!(
+                    "advancement revoke @s only {}:{}",
+                    namespace, name
+                )),
+                Event::Score { first_join, .. } => {
+                    let objective = handler.objective();
+                    reward.append_command(&format!("scoreboard players reset @s {}", objective));
+                    tick.append_command(&format!(
+                        "execute as @a[scores={{{}=1..}}] at @s run function {}:{}",
+                        objective, namespace, handler.name
+                    ));
+                    if *first_join {
+                        reward.append_command(&format!("tag @s add {}", objective));
+                        tick.append_command(&format!(
+                            "execute as @a[tag=!{}] at @s run function {}:{}",
+                            objective, namespace, handler.name
+                        ));
+                    }
+                }
+            }
+            reward.append_command(&format!("function {}:{}", namespace, handler.func_ident));
+            mcfunctions.push(reward);
+        }
+        if !tick.commands().is_empty() {
+            mcfunctions.push(tick);
+        }
+        for mcfunction in mcfunctions {
+            self.working_namespace().append_mcfunction(mcfunction);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::frontend::ast::{exp::ExpType, Block, ExecuteModifier, SrcLocation};
+
+use super::{Generator, SemanticError};
+
+/// The subcommands of `execute` that change the context, without ending it.
+const MODIFIERS: [&str; 10] = [
+    "align",
+    "anchored",
+    "as",
+    "at",
+    "facing",
+    "in",
+    "on",
+    "positioned",
+    "rotated",
+    "summon",
+];
+
+impl Generator {
+    /// Compiles `execute <modifiers> { body }`. The body goes to a new label,
+    /// which `execute ... run function` calls with the current frame, so the
+    /// body may read and write the locals of the function. Selectors only
+    /// known at runtime are passed to the `execute` command as macro
+    /// arguments.
+    pub(super) fn execute_block(
+        &mut self,
+        modifiers: &mut [ExecuteModifier],
+        body: &mut Block,
+    ) -> Result<(), SemanticError> {
+        let mut reg_acc = self.new_reg_acc();
+        let mut obj_acc = self.new_obj_acc();
+        let mut subcommands = vec![];
+        let mut selector_vals = vec![];
+        for modifier in modifiers {
+            if !MODIFIERS.contains(&modifier.ident.string.as_str()) {
+                return Err(SemanticError::UnknownExecuteModifier {
+                    modifier: modifier.ident.string.to_owned(),
+                    begin: modifier.ident.src_loc.begin,
+                    end: modifier.ident.src_loc.end,
+                });
+            }
+            let argument = match &modifier.argument.exp_type {
+                ExpType::StringLiteral(text) => text.to_owned(),
+                _ => self.selector_argument(
+                    &mut modifier.argument,
+                    &mut selector_vals,
+                    &mut reg_acc,
+                    &mut obj_acc,
+                )?,
+            };
+            subcommands.push(format!("{} {}", modifier.ident.string, argument));
+        }
+
+        let label_body = self.new_label();
+        let command = format!(
+            "execute {} run function {}:{}{}",
+            subcommands.join(" "),
+            self.working_namespace_name(),
+            label_body.name(),
+            self.label_call_arguments()
+        );
+        self.append_macro_command(&command, &selector_vals);
+
+        // body, which runs once for each context; `break` and `continue`
+        // cannot leave it, since the label may run more than once
+        let caller = self.working_mcfunction.replace(label_body);
+        self.append_abort_check();
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let in_execute_block = std::mem::replace(&mut self.in_execute_block, true);
+        self.variable_table.enter_scope();
+        self.tail_position = false;
+        self.generate_from_block(body, &None)?;
+        self.variable_table.leave_scope();
+        self.in_execute_block = in_execute_block;
+        self.loop_depth = loop_depth;
+        let last_label = std::mem::replace(&mut self.working_mcfunction, caller);
+        self.working_namespace()
+            .append_mcfunction(last_label.unwrap());
+        self.append_abort_check();
+        Ok(())
+    }
+
+    /// `return`, `yield` and `sleep` would only leave the body of the execute
+    /// block, which is called once for each context.
+    pub(super) fn check_not_in_execute_block(
+        &self,
+        statement: &str,
+        src_loc: &SrcLocation,
+    ) -> Result<(), SemanticError> {
+        if self.in_execute_block {
+            return Err(SemanticError::NotAllowedInExecuteBlock {
+                statement: statement.into(),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+        Ok(())
+    }
+}
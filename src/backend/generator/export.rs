@@ -0,0 +1,50 @@
+use crate::{
+    backend::datapack::Mcfunction,
+    frontend::ast::{DataType, FuncDef},
+};
+
+use super::Generator;
+
+/// The name of the wrapper of an `#[export]` function.
+pub(super) fn wrapper_name(func_ident: &str) -> String {
+    format!("api/{}", func_ident)
+}
+
+impl Generator {
+    /// Generates `ns:api/f`, which takes the parameters of `f` as macro
+    /// arguments named after them, e.g. `function ns:api/add {a: 1, b: 2}`.
+    /// The return value is copied to `f` in storage `ns:api`, and an `int`
+    /// is also returned by the wrapper itself.
+    pub(super) fn append_export_wrapper(&mut self, func_def: &FuncDef) {
+        let namespace = self.working_namespace_name().to_owned();
+        let func_ident = &func_def.ident.string;
+        let mut wrapper = Mcfunction::new(wrapper_name(func_ident));
+        if !func_def.params.is_empty() {
+            let arguments: Vec<_> = func_def
+                .params
+                .iter()
+                .enumerate()
+                .map(|(i, param)| match param.data_type {
+                    // a selector is pasted as it is, so it must be quoted
+                    DataType::Selector => format!("\"%{}\": \"$({})\"", i, param.ident.string),
+                    _ => format!("\"%{}\": $({})", i, param.ident.string),
+                })
+                .collect();
+            wrapper.append_command(&format!(
+                "data modify storage memory:temp arguments set value {{{}}}",
+                arguments.join(", ")
+            ));
+        }
+        wrapper.append_command(&format!("function {}:{}", namespace, func_ident));
+        if let Some(func_type) = &func_def.func_type {
+            wrapper.append_command(&format!(
+                "data modify storage {}:api {} set from storage memory:temp return_value",
+                namespace, func_ident
+            ));
+            if *func_type == DataType::Int {
+                wrapper.append_command("return run data get storage memory:temp return_value");
+            }
+        }
+        self.working_namespace().append_mcfunction(wrapper);
+    }
+}
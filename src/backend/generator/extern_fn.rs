@@ -0,0 +1,91 @@
+use crate::frontend::ast::{exp::Exp, DataType, FuncDef};
+
+use super::{call_graph, ExpVal, Generator, Location, ObjAcc, RegAcc, SemanticError};
+
+impl Generator {
+    /// Calls the `extern fn` at `path`. The arguments are passed as macro
+    /// arguments named after the parameters, and the return value is read
+    /// from `memory:temp return_value`. The function may use the registers
+    /// or call MCScript functions, so the registers in use are saved like
+    /// for other calls, and the int locals as well if it may call the
+    /// current function again.
+    pub(super) fn extern_call(
+        &mut self,
+        path: &str,
+        func_def: FuncDef,
+        arguments: &mut [Box<Exp>],
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        for i in 0..reg_acc.cnt {
+            self.mov(
+                &self.frame.location(&format!("%r{}", i)),
+                &Location::Register(format!("r{}", i)),
+            );
+        }
+        let mut argument_vals = vec![];
+        let mut reg_acc_1 = RegAcc::new();
+        for (i, arg) in arguments.iter_mut().enumerate() {
+            let exp_val = self.eval(arg, &mut reg_acc_1, obj_acc)?;
+            if exp_val.data_type != func_def.params[i].data_type {
+                return Err(SemanticError::TypeMismatch {
+                    expected_type: func_def.params[i].data_type.clone(),
+                    found_type: exp_val.data_type.clone(),
+                    begin: arg.src_loc.begin,
+                    end: arg.src_loc.end,
+                });
+            }
+            argument_vals.push(exp_val);
+        }
+
+        let may_reenter = self.may_reenter(&call_graph::outside());
+        if may_reenter {
+            self.store_int_locals();
+        }
+        // the arguments are only moved here, since evaluating one of them
+        // may call another `extern fn`
+        if argument_vals.is_empty() {
+            self.working_mcfunction()
+                .append_command(&format!("function {}", path));
+        } else {
+            self.working_mcfunction()
+                .append_command("data modify storage memory:temp extern_arguments set value {}");
+            for (param, exp_val) in func_def.params.iter().zip(argument_vals) {
+                self.mov(
+                    &Location::Memory(
+                        "memory:temp".into(),
+                        format!("extern_arguments.{}", param.ident.string),
+                    ),
+                    &exp_val.location,
+                );
+            }
+            self.working_mcfunction().append_command(&format!(
+                "function {} with storage memory:temp extern_arguments",
+                path
+            ));
+        }
+        self.append_abort_check();
+        if may_reenter {
+            self.load_int_locals();
+        }
+        for i in 0..reg_acc.cnt {
+            self.mov(
+                &Location::Register(format!("r{}", i)),
+                &self.frame.location(&format!("%r{}", i)),
+            );
+        }
+
+        let location = match &func_def.func_type {
+            Some(DataType::Int) => reg_acc.new_reg(),
+            Some(DataType::Array { .. } | DataType::Selector) => obj_acc.new_obj(&self.frame),
+            None => Location::return_value(),
+        };
+        if func_def.func_type.is_some() {
+            self.mov(&location, &Location::return_value());
+        }
+        Ok(ExpVal {
+            data_type: func_def.func_type.unwrap_or(DataType::Int),
+            location,
+        })
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::ast::{
+    exp::{ArrayMethodType, Exp, ExpType},
+    Block, BlockItem, CompileUnit, DataType, FuncDef, GlobalDef, Stmt,
+};
+
+use super::{control_flow, ExpVal, Generator, Location, ObjAcc, RegAcc, SemanticError};
+
+/// Functions whose body has at most this many statements and expressions are
+/// inlined even without `#[inline]`.
+const INLINE_SIZE_LIMIT: usize = 32;
+
+/// How `return` is compiled inside the body of an inlined function.
+pub(super) struct InlineReturn {
+    pub result: Option<Location>,
+    /// Whether the body is in a label of its own that `return` has to leave.
+    pub exits_label: bool,
+}
+
+/// Finds the functions whose calls are replaced by their bodies: the
+/// functions that are marked `#[inline]` or small, except recursive ones,
+/// tasks and ones that may reach the end of their body without returning a
+/// value.
+pub(super) fn inline_functions(
+    compile_units: &[(CompileUnit, String)],
+    recursive_functions: &HashMap<(String, String), usize>,
+) -> HashSet<(String, String)> {
+    let mut inline_functions = HashSet::new();
+    for (compile_unit, namespace) in compile_units {
+        for global_def in &compile_unit.global_defs {
+            if let GlobalDef::FuncDef(func_def) = global_def {
+                let function_id = (namespace.to_owned(), func_def.ident.string.to_owned());
+                let marked_inline = func_def
+                    .attributes
+                    .iter()
+                    .any(|attribute| attribute.ident.string == "inline");
+                let always_returns = func_def.func_type.is_none()
+                    || matches!(
+                        func_def.block.0.last(),
+                        Some(BlockItem::Stmt(Stmt::Return { .. }))
+                    );
+                if !recursive_functions.contains_key(&function_id)
+                    && !control_flow::block_may_suspend(&func_def.block)
+                    && always_returns
+                    && (marked_inline || block_size(&func_def.block) <= INLINE_SIZE_LIMIT)
+                {
+                    inline_functions.insert(function_id);
+                }
+            }
+        }
+    }
+    inline_functions
+}
+
+impl Generator {
+    /// Generates the body of `func_def` in place of a call to it. The
+    /// parameters become fresh locals of the caller, and the body can only
+    /// see them and the globals of `namespace`.
+    pub(super) fn inline_call(
+        &mut self,
+        namespace: &str,
+        mut func_def: FuncDef,
+        arguments: &mut [Box<Exp>],
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        let mut argument_vals = vec![];
+        for (i, arg) in arguments.iter_mut().enumerate() {
+            let exp_val = self.eval(arg, reg_acc, obj_acc)?;
+            if exp_val.data_type != func_def.params[i].data_type {
+                return Err(SemanticError::TypeMismatch {
+                    expected_type: func_def.params[i].data_type.clone(),
+                    found_type: exp_val.data_type.clone(),
+                    begin: arg.src_loc.begin,
+                    end: arg.src_loc.end,
+                });
+            }
+            argument_vals.push(exp_val);
+        }
+        let result = match &func_def.func_type {
+            Some(DataType::Int) => Some(reg_acc.new_reg()),
+            Some(DataType::Array { .. } | DataType::Selector) => Some(obj_acc.new_obj(&self.frame)),
+            None => None,
+        };
+
+        let exits_label = has_early_return(&func_def.block);
+        let inline_return = self.inline_return.replace(InlineReturn {
+            result: result.clone(),
+            exits_label,
+        });
+        let scope_namespace = std::mem::replace(&mut self.scope_namespace, namespace.to_owned());
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        let in_execute_block = std::mem::take(&mut self.in_execute_block);
+        let register_base = std::mem::replace(&mut self.register_base, reg_acc.cnt);
+        let object_base = std::mem::replace(&mut self.object_base, obj_acc.cnt);
+        let first_visible_scope = self.variable_table.hide_outer_scopes();
+
+        self.variable_table.enter_scope();
+        for (param, argument_val) in func_def.params.iter().zip(argument_vals) {
+            let variable = self
+                .variable_table
+                .new_local_variable(&param.ident, param.data_type.clone())?;
+            self.mov(&variable.location(&self.frame), &argument_val.location);
+        }
+        if exits_label {
+            let label = self.new_label();
+            let command = format!(
+                "function {}:{}{}",
+                self.working_namespace_name(),
+                label.name(),
+                self.label_call_arguments()
+            );
+            self.working_mcfunction().append_command(&command);
+            let caller = self.working_mcfunction.replace(label);
+            self.generate_from_block(&mut func_def.block, &func_def.func_type)?;
+            let last_label = std::mem::replace(&mut self.working_mcfunction, caller);
+            self.working_namespace()
+                .append_mcfunction(last_label.unwrap());
+            self.append_abort_check();
+        } else {
+            self.generate_from_block(&mut func_def.block, &func_def.func_type)?;
+        }
+        self.variable_table.leave_scope();
+
+        self.variable_table.show_outer_scopes(first_visible_scope);
+        self.object_base = object_base;
+        self.register_base = register_base;
+        self.loop_depth = loop_depth;
+        self.in_execute_block = in_execute_block;
+        self.scope_namespace = scope_namespace;
+        self.inline_return = inline_return;
+
+        Ok(ExpVal {
+            data_type: func_def.func_type.unwrap_or(DataType::Int),
+            location: result.unwrap_or(Location::return_value()),
+        })
+    }
+}
+
+/// Whether some `return` in `block` is not its last statement, so the rest
+/// of the body has to be skipped.
+fn has_early_return(block: &Block) -> bool {
+    match block.0.split_last() {
+        Some((BlockItem::Stmt(Stmt::Return { .. }), rest)) => {
+            control_flow::block_contains_return(&Block(rest.to_vec()))
+        }
+        _ => control_flow::block_contains_return(block),
+    }
+}
+
+fn block_size(block: &Block) -> usize {
+    block
+        .0
+        .iter()
+        .map(|block_item| match block_item {
+            BlockItem::Decl(decl) => 1 + exp_size(&decl.init_value),
+            BlockItem::Stmt(stmt) => stmt_size(stmt),
+        })
+        .sum()
+}
+
+fn stmt_size(stmt: &Stmt) -> usize {
+    1 + match stmt {
+        Stmt::Return { return_value, .. } => return_value.as_ref().map_or(0, |exp| exp_size(exp)),
+        Stmt::Assign { lhs, new_value } => exp_size(lhs) + exp_size(new_value),
+        Stmt::Block(block) => block_size(block),
+        Stmt::IfElse {
+            exp,
+            if_branch,
+            else_branch,
+        } => exp_size(exp) + block_size(if_branch) + else_branch.as_ref().map_or(0, block_size),
+        Stmt::While { exp, body } => exp_size(exp) + block_size(body),
+        Stmt::Execute {
+            modifiers, body, ..
+        } => {
+            let arguments_size: usize = modifiers
+                .iter()
+                .map(|modifier| exp_size(&modifier.argument))
+                .sum();
+            arguments_size + block_size(body)
+        }
+        Stmt::Break { .. } | Stmt::Continue { .. } | Stmt::Yield { .. } => 0,
+        Stmt::Sleep { ticks, .. } => exp_size(ticks),
+        Stmt::Exp(exp) => exp_size(exp),
+        Stmt::InlineCommand { arguments, .. } => arguments.iter().map(|arg| exp_size(arg)).sum(),
+    }
+}
+
+fn exp_size(exp: &Exp) -> usize {
+    1 + match &exp.exp_type {
+        ExpType::Number(_)
+        | ExpType::Variable { .. }
+        | ExpType::Running { .. }
+        | ExpType::StringLiteral(_)
+        | ExpType::Selector(_) => 0,
+        ExpType::SelectorWith {
+            selector,
+            arguments,
+            ..
+        } => exp_size(selector) + arguments.iter().map(|arg| exp_size(arg)).sum::<usize>(),
+        ExpType::UnaryExp(_, exp) => exp_size(exp),
+        ExpType::BinaryExp(_, lhs, rhs) => exp_size(lhs) + exp_size(rhs),
+        ExpType::ArrayElement { array, subscript } => exp_size(array) + exp_size(subscript),
+        ExpType::ArrayMethod { array, method } => {
+            exp_size(array)
+                + match method {
+                    ArrayMethodType::Size | ArrayMethodType::Pop => 0,
+                    ArrayMethodType::Push { value } => exp_size(value),
+                    ArrayMethodType::Insert { pos, value } => exp_size(pos) + exp_size(value),
+                    ArrayMethodType::Erase { pos } => exp_size(pos),
+                }
+        }
+        ExpType::FuncCall { arguments, .. } => arguments.iter().map(|arg| exp_size(arg)).sum(),
+        ExpType::NewArray { length, element } => exp_size(length) + exp_size(element),
+        ExpType::SquareBracketsArray { elements, .. } => {
+            elements.iter().map(|element| exp_size(element)).sum()
+        }
+    }
+}
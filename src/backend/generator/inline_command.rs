@@ -0,0 +1,329 @@
+use crate::frontend::ast::{
+    exp::{Exp, ExpType},
+    Ident, SrcLocation,
+};
+
+use super::{call_graph, Generator, Location, SemanticError};
+
+/// The commands of vanilla Minecraft, with the versions they were added in.
+const COMMANDS: [(&str, (u32, u32, u32)); 90] = [
+    ("advancement", (1, 12, 0)),
+    ("attribute", (1, 16, 0)),
+    ("ban", (1, 0, 0)),
+    ("ban-ip", (1, 0, 0)),
+    ("banlist", (1, 0, 0)),
+    ("bossbar", (1, 13, 0)),
+    ("clear", (1, 0, 0)),
+    ("clone", (1, 0, 0)),
+    ("damage", (1, 19, 4)),
+    ("data", (1, 13, 0)),
+    ("datapack", (1, 13, 0)),
+    ("debug", (1, 0, 0)),
+    ("defaultgamemode", (1, 0, 0)),
+    ("deop", (1, 0, 0)),
+    ("dialog", (1, 21, 6)),
+    ("difficulty", (1, 0, 0)),
+    ("effect", (1, 0, 0)),
+    ("enchant", (1, 0, 0)),
+    ("execute", (1, 0, 0)),
+    ("experience", (1, 13, 0)),
+    ("fill", (1, 0, 0)),
+    ("fillbiome", (1, 19, 3)),
+    ("forceload", (1, 14, 4)),
+    ("function", (1, 12, 0)),
+    ("gamemode", (1, 0, 0)),
+    ("gamerule", (1, 0, 0)),
+    ("give", (1, 0, 0)),
+    ("help", (1, 0, 0)),
+    ("item", (1, 17, 0)),
+    ("jfr", (1, 18, 0)),
+    ("kick", (1, 0, 0)),
+    ("kill", (1, 0, 0)),
+    ("list", (1, 0, 0)),
+    ("locate", (1, 11, 0)),
+    ("locatebiome", (1, 16, 0)),
+    ("loot", (1, 14, 0)),
+    ("me", (1, 0, 0)),
+    ("msg", (1, 0, 0)),
+    ("op", (1, 0, 0)),
+    ("pardon", (1, 0, 0)),
+    ("pardon-ip", (1, 0, 0)),
+    ("particle", (1, 0, 0)),
+    ("perf", (1, 17, 0)),
+    ("place", (1, 19, 0)),
+    ("playsound", (1, 0, 0)),
+    ("publish", (1, 0, 0)),
+    ("random", (1, 20, 2)),
+    ("recipe", (1, 12, 0)),
+    ("reload", (1, 0, 0)),
+    ("replaceitem", (1, 8, 0)),
+    ("return", (1, 20, 2)),
+    ("ride", (1, 19, 4)),
+    ("rotate", (1, 21, 2)),
+    ("save-all", (1, 0, 0)),
+    ("save-off", (1, 0, 0)),
+    ("save-on", (1, 0, 0)),
+    ("say", (1, 0, 0)),
+    ("schedule", (1, 14, 0)),
+    ("scoreboard", (1, 0, 0)),
+    ("seed", (1, 0, 0)),
+    ("setblock", (1, 0, 0)),
+    ("setidletimeout", (1, 0, 0)),
+    ("setworldspawn", (1, 0, 0)),
+    ("spawnpoint", (1, 0, 0)),
+    ("spectate", (1, 15, 0)),
+    ("spreadplayers", (1, 0, 0)),
+    ("stop", (1, 0, 0)),
+    ("stopsound", (1, 0, 0)),
+    ("summon", (1, 0, 0)),
+    ("tag", (1, 13, 0)),
+    ("team", (1, 13, 0)),
+    ("teammsg", (1, 14, 0)),
+    ("teleport", (1, 0, 0)),
+    ("tell", (1, 0, 0)),
+    ("tellraw", (1, 0, 0)),
+    ("test", (1, 21, 5)),
+    ("tick", (1, 20, 3)),
+    ("time", (1, 0, 0)),
+    ("title", (1, 0, 0)),
+    ("tm", (1, 14, 0)),
+    ("tp", (1, 0, 0)),
+    ("transfer", (1, 20, 5)),
+    ("trigger", (1, 0, 0)),
+    ("version", (1, 21, 6)),
+    ("w", (1, 0, 0)),
+    ("waypoint", (1, 21, 6)),
+    ("weather", (1, 0, 0)),
+    ("whitelist", (1, 0, 0)),
+    ("worldborder", (1, 0, 0)),
+    ("xp", (1, 0, 0)),
+];
+
+/// The versions in which commands were removed.
+const REMOVED_COMMANDS: [(&str, (u32, u32, u32)); 2] = [
+    ("locatebiome", (1, 19, 0)),
+    ("replaceitem", (1, 17, 0)),
+];
+
+enum Piece {
+    Text(String),
+    /// `{}` or `{0}`, an argument after the format string.
+    Argument(usize),
+    /// `{name}`, a variable in scope.
+    Variable(String),
+}
+
+/// Splits `fmt_str` into text and placeholders: `{}` is the next argument,
+/// `{0}` the first one and `{name}` the variable `name`, if `is_variable`
+/// says it is in scope. `{{` and the `}}` closing it are literal braces.
+/// Other braces, e.g. of NBT compounds, are kept as they are.
+fn parse_format_string(fmt_str: &str, is_variable: impl Fn(&str) -> bool) -> Vec<Piece> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut next_argument = 0;
+    let mut escaped_braces = 0;
+    let mut rest = fmt_str;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("{{") {
+            text.push('{');
+            escaped_braces += 1;
+            rest = after;
+            continue;
+        }
+        if escaped_braces > 0 {
+            if let Some(after) = rest.strip_prefix("}}") {
+                text.push('}');
+                escaped_braces -= 1;
+                rest = after;
+                continue;
+            }
+        }
+        if c == '{' {
+            if let Some((name, after)) = rest[1..].split_once('}') {
+                let placeholder = if name.is_empty() {
+                    next_argument += 1;
+                    Some(Piece::Argument(next_argument - 1))
+                } else if name.chars().all(|c| c.is_ascii_digit()) {
+                    name.parse().ok().map(Piece::Argument)
+                } else if !name.starts_with(|c: char| c.is_ascii_digit())
+                    && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+                    && is_variable(name)
+                {
+                    Some(Piece::Variable(name.to_owned()))
+                } else {
+                    None
+                };
+                if let Some(placeholder) = placeholder {
+                    if !text.is_empty() {
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                    }
+                    pieces.push(placeholder);
+                    rest = after;
+                    continue;
+                }
+            }
+        }
+        text.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    pieces
+}
+
+/// Checks that the first word of `command` is a command of Minecraft
+/// `version`. Only commands that start with text are checked: if the first
+/// piece of the format string is a placeholder, the root isn't known until
+/// the command runs.
+fn validate_command(command: &str, version: (u32, u32, u32)) -> Result<(), String> {
+    let root = command.split(' ').next().unwrap_or_default();
+    let Some((_, added)) = COMMANDS.iter().find(|(name, _)| *name == root) else {
+        return Err(format!("unknown command \"{}\"", root));
+    };
+    let removed = REMOVED_COMMANDS
+        .iter()
+        .find(|(name, _)| *name == root)
+        .map(|(_, removed)| *removed);
+    if version < *added || removed.is_some_and(|removed| version >= removed) {
+        return Err(format!(
+            "command \"{}\" does not exist in Minecraft {}.{}.{}",
+            root, version.0, version.1, version.2
+        ));
+    }
+    Ok(())
+}
+
+impl Generator {
+    /// Compiles `run_command!(fmt_str, arguments...)`, which runs as a macro
+    /// line with the arguments and the variables named in `fmt_str`.
+    pub(super) fn inline_command(
+        &mut self,
+        is_mod_command: bool,
+        fmt_str: &str,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+    ) -> Result<(), SemanticError> {
+        let pieces = parse_format_string(fmt_str, |name| {
+            let ident = Ident {
+                string: name.to_owned(),
+                src_loc: src_loc.clone(),
+            };
+            self.variable_table
+                .query_variable(&ident, &None, &self.scope_namespace)
+                .is_ok()
+        });
+        let needed_count = pieces
+            .iter()
+            .filter_map(|piece| match piece {
+                Piece::Argument(i) => Some(i + 1),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        if needed_count > arguments.len() {
+            return Err(SemanticError::InvalidFormatString {
+                message: format!(
+                    "the format string needs {} argument(s), found {}",
+                    needed_count,
+                    arguments.len()
+                ),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+        let mut used = vec![false; arguments.len()];
+        for piece in &pieces {
+            if let Piece::Argument(i) = piece {
+                used[*i] = true;
+            }
+        }
+        if let Some(i) = used.iter().position(|used| !used) {
+            return Err(SemanticError::InvalidFormatString {
+                message: "argument never used in the format string".into(),
+                begin: arguments[i].src_loc.begin,
+                end: arguments[i].src_loc.end,
+            });
+        }
+        if !is_mod_command {
+            if let Some(Piece::Text(text)) = pieces.first() {
+                validate_command(text, self.mc_version).map_err(|message| {
+                    SemanticError::InvalidFormatString {
+                        message,
+                        begin: src_loc.begin,
+                        end: src_loc.end,
+                    }
+                })?;
+            }
+        }
+
+        let mut variables = vec![];
+        let mut cmd = String::new();
+        for piece in &pieces {
+            match piece {
+                Piece::Text(text) => cmd.push_str(text),
+                Piece::Argument(i) => cmd.push_str(&format!("$({})", i)),
+                Piece::Variable(name) => {
+                    let i = match variables.iter().position(|variable| variable == name) {
+                        Some(i) => i,
+                        None => {
+                            variables.push(name.to_owned());
+                            variables.len() - 1
+                        }
+                    };
+                    cmd.push_str(&format!("$({})", arguments.len() + i));
+                }
+            }
+        }
+        if is_mod_command && arguments.is_empty() && variables.is_empty() {
+            cmd.push_str("$(empty_str)");
+        }
+        let mut variables = variables
+            .into_iter()
+            .map(|name| Exp {
+                exp_type: ExpType::Variable {
+                    ident: Ident {
+                        string: name,
+                        src_loc: src_loc.clone(),
+                    },
+                    namespace: None,
+                },
+                src_loc: src_loc.clone(),
+            })
+            .collect::<Vec<_>>();
+        for (i, arg) in arguments
+            .iter_mut()
+            .map(|arg| arg.as_mut())
+            .chain(variables.iter_mut())
+            .enumerate()
+        {
+            let exp_val = self.eval(arg, &mut self.new_reg_acc(), &mut self.new_obj_acc())?;
+            self.mov(
+                &Location::Memory(
+                    "memory:temp".into(),
+                    format!("custom_command_arguments.{}", i),
+                ),
+                &exp_val.location,
+            );
+        }
+        let custom_cmd = self.append_custom_command(&cmd);
+        if is_mod_command {
+            self.mod_commands.insert(custom_cmd.clone());
+        }
+        let may_reenter =
+            call_graph::command_callees(fmt_str, |callee| self.is_mcscript_function(callee))
+                .iter()
+                .any(|callee| self.may_reenter(callee));
+        if may_reenter {
+            self.store_int_locals();
+        }
+        self.working_mcfunction().append_commands(vec![&format!(
+            "function {} with storage memory:temp custom_command_arguments",
+            custom_cmd
+        )]);
+        if may_reenter {
+            self.load_int_locals();
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! JSON resources such as advancements and loot tables, declared with
+//! `resource`.
+
+use std::{collections::HashSet, fs::read_to_string};
+
+use serde_json::Value;
+
+use crate::{
+    backend::datapack::Resource,
+    frontend::ast::{CompileUnit, GlobalDef, ResourceDef, ResourceJson},
+};
+
+use super::{Generator, SemanticError};
+
+/// The kinds of resources besides tags, which are `tags/<registry>`.
+const RESOURCE_KINDS: [&str; 5] = [
+    "advancement",
+    "item_modifier",
+    "loot_table",
+    "predicate",
+    "recipe",
+];
+
+fn is_resource_path(path: &str) -> bool {
+    !path.is_empty()
+        && path.split('/').all(|segment| {
+            !segment.is_empty()
+                && segment
+                    .chars()
+                    .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '_' | '-' | '.'))
+        })
+}
+
+/// The function ids in `json`: the `function` of advancement rewards, and
+/// the values of function tags.
+fn referenced_functions<'a>(json: &'a Value, is_function_tag: bool, ids: &mut Vec<&'a str>) {
+    match json {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match value {
+                    Value::String(id) if key == "function" => ids.push(id),
+                    Value::Array(values) if is_function_tag && key == "values" => {
+                        for value in values {
+                            match value {
+                                Value::String(id) => ids.push(id),
+                                Value::Object(entry) => {
+                                    if let Some(Value::String(id)) = entry.get("id") {
+                                        ids.push(id);
+                                    }
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => referenced_functions(value, is_function_tag, ids),
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                referenced_functions(value, is_function_tag, ids);
+            }
+        }
+        _ => {}
+    }
+}
+
+impl Generator {
+    /// Reads and checks the resources of all namespaces. The functions they
+    /// reference are called from outside the datapack.
+    pub(super) fn scan_resources(
+        &mut self,
+        compile_units: &[(CompileUnit, String)],
+    ) -> Result<(), (String, SemanticError)> {
+        let namespaces = compile_units
+            .iter()
+            .map(|(_, namespace)| namespace.as_str())
+            .collect::<HashSet<_>>();
+        for (compile_unit, namespace) in compile_units {
+            let mut paths = HashSet::new();
+            for global_def in &compile_unit.global_defs {
+                if let GlobalDef::ResourceDef(resource_def) = global_def {
+                    let resource = self
+                        .scan_resource(resource_def, &namespaces, &mut paths)
+                        .map_err(|message| {
+                            (
+                                namespace.to_owned(),
+                                SemanticError::InvalidResource {
+                                    message,
+                                    begin: resource_def.src_loc.begin,
+                                    end: resource_def.src_loc.end,
+                                },
+                            )
+                        })?;
+                    self.resources
+                        .entry(namespace.to_owned())
+                        .or_default()
+                        .push(resource);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn scan_resource(
+        &mut self,
+        resource_def: &ResourceDef,
+        namespaces: &HashSet<&str>,
+        paths: &mut HashSet<String>,
+    ) -> Result<Resource, String> {
+        let kind = resource_def.kind.string.as_str();
+        let is_tag = kind
+            .strip_prefix("tags/")
+            .is_some_and(|registry| is_resource_path(registry) && !registry.contains('/'));
+        if !is_tag && !RESOURCE_KINDS.contains(&kind) {
+            return Err(format!(
+                "unknown resource kind \"{}\", expected one of {} or tags/<registry>",
+                kind,
+                RESOURCE_KINDS.join(", ")
+            ));
+        }
+        if !is_resource_path(&resource_def.name) {
+            return Err(format!("invalid resource name \"{}\"", resource_def.name));
+        }
+        if !paths.insert(format!("{}/{}", kind, resource_def.name)) {
+            return Err(format!(
+                "{} \"{}\" is defined more than once",
+                kind, resource_def.name
+            ));
+        }
+        let text = match &resource_def.json {
+            ResourceJson::Inline(text) => text.to_owned(),
+            ResourceJson::Include(path) => {
+                read_to_string(path).map_err(|err| format!("cannot read \"{}\": {}", path, err))?
+            }
+        };
+        let json: Value =
+            serde_json::from_str(&text).map_err(|err| format!("invalid JSON: {}", err))?;
+        if !json.is_object() {
+            return Err("a resource must be a JSON object".into());
+        }
+
+        let mut ids = vec![];
+        referenced_functions(&json, kind == "tags/function", &mut ids);
+        for id in ids {
+            let Some((namespace, name)) = id.split_once(':') else {
+                continue;
+            };
+            if !namespaces.contains(namespace) {
+                continue;
+            }
+            let key = (namespace.to_owned(), name.to_owned());
+            let func_def = self
+                .function_table
+                .0
+                .get(&key)
+                .filter(|_| !self.extern_paths.contains_key(&key))
+                .ok_or_else(|| format!("unknown function \"{}\"", id))?;
+            if !func_def.params.is_empty() {
+                return Err(format!(
+                    "function \"{}\" is called by the resource, so it cannot have parameters",
+                    id
+                ));
+            }
+            self.entry_points.push(id.to_owned());
+            if kind == "advancement" {
+                self.reward_functions.insert(key.clone());
+            }
+            self.external_functions.insert(key);
+        }
+
+        Ok(Resource::new(
+            kind.to_owned(),
+            resource_def.name.to_owned(),
+            text,
+        ))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::frontend::{
+    ast::{exp::Exp, DataType, SrcLocation},
+    selector::validate_selector_argument,
+};
+
+use super::{macro_command, ExpVal, Generator, Location, ObjAcc, RegAcc, SemanticError};
+
+impl Generator {
+    /// A selector is stored as a string, which is pasted as it is when
+    /// passed to a macro.
+    pub(super) fn eval_selector(&mut self, selector: &str, obj_acc: &mut ObjAcc) -> ExpVal {
+        let obj = obj_acc.new_obj(&self.frame);
+        self.mov_immediate(
+            &obj,
+            &format!("\"{}\"", selector.replace('\\', "\\\\")),
+            obj_acc,
+        );
+        ExpVal {
+            data_type: DataType::Selector,
+            location: obj,
+        }
+    }
+
+    /// Compiles `selector.with("key=value", arguments...)`. `mcscript:selector_split`
+    /// cuts the closing bracket off the selector, then a macro puts the new
+    /// argument in its place.
+    pub(super) fn eval_selector_with(
+        &mut self,
+        selector: &mut Exp,
+        argument: &str,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        validate_selector_argument(argument).map_err(|message| SemanticError::InvalidSelector {
+            message,
+            begin: src_loc.begin,
+            end: src_loc.end,
+        })?;
+        let (command, placeholder_cnt) = macro_command(argument);
+        if placeholder_cnt != arguments.len() {
+            return Err(SemanticError::FuncArgumentsCountMismatch {
+                expected_count: placeholder_cnt,
+                found_count: arguments.len(),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+
+        let selector_val = self.eval(selector, reg_acc, obj_acc)?;
+        if selector_val.data_type != DataType::Selector {
+            return Err(SemanticError::TypeMismatch {
+                expected_type: DataType::Selector,
+                found_type: selector_val.data_type,
+                begin: selector.src_loc.begin,
+                end: selector.src_loc.end,
+            });
+        }
+        let mut argument_vals = vec![];
+        for arg in arguments.iter_mut() {
+            let exp_val = self.eval(arg, reg_acc, obj_acc)?;
+            if exp_val.data_type != DataType::Int {
+                return Err(SemanticError::TypeMismatch {
+                    expected_type: DataType::Int,
+                    found_type: exp_val.data_type,
+                    begin: arg.src_loc.begin,
+                    end: arg.src_loc.end,
+                });
+            }
+            argument_vals.push(exp_val);
+        }
+
+        let temp_selector = Location::Memory("memory:temp".into(), "selector".into());
+        self.mov(&temp_selector, &selector_val.location);
+        for (i, exp_val) in argument_vals.iter().enumerate() {
+            self.mov(
+                &Location::Memory(
+                    "memory:temp".into(),
+                    format!("custom_command_arguments.{}", i),
+                ),
+                &exp_val.location,
+            );
+        }
+        self.working_mcfunction()
+            .append_command("function mcscript:selector_split");
+        let custom_cmd = self.append_custom_command(&format!(
+            "data modify storage memory:temp selector set value \"$(head)$(separator){}]\"",
+            command
+        ));
+        self.working_mcfunction().append_command(&format!(
+            "function {} with storage memory:temp custom_command_arguments",
+            custom_cmd
+        ));
+        let obj = obj_acc.new_obj(&self.frame);
+        self.mov(&obj, &temp_selector);
+        Ok(ExpVal {
+            data_type: DataType::Selector,
+            location: obj,
+        })
+    }
+}
@@ -0,0 +1,82 @@
+use crate::frontend::ast::exp::{Exp, ExpType};
+
+use super::{Frame, Generator, Location, SemanticError};
+
+impl Generator {
+    /// Whether `return exp;` is a call of the function being generated that
+    /// can reuse the current frame.
+    pub(super) fn is_self_tail_call(&self, exp: &Exp) -> bool {
+        let ExpType::FuncCall {
+            namespace,
+            func_ident,
+            ..
+        } = &exp.exp_type
+        else {
+            return false;
+        };
+        let namespace = namespace
+            .as_ref()
+            .map_or(self.scope_namespace.as_str(), |namespace| {
+                namespace.string.as_str()
+            });
+        // Inside a loop, the loop's driver is still waiting for the iteration
+        // to finish.
+        self.inline_return.is_none()
+            && self.loop_depth == 0
+            && matches!(self.frame, Frame::Stack(_))
+            && namespace == self.working_namespace_name()
+            && func_ident.string == self.working_function_ident
+    }
+
+    /// Compiles `return f(...);` inside `f` into a jump to its first label,
+    /// after overwriting the parameters in the current frame.
+    pub(super) fn tail_call(&mut self, exp: &mut Exp) -> Result<(), SemanticError> {
+        let ExpType::FuncCall {
+            func_ident,
+            arguments,
+            ..
+        } = &mut exp.exp_type
+        else {
+            unreachable!()
+        };
+        let namespace = self.working_namespace_name().to_owned();
+        let func_def = self
+            .function_table
+            .query_function(&namespace, func_ident)?
+            .clone();
+        if func_def.params.len() != arguments.len() {
+            return Err(SemanticError::FuncArgumentsCountMismatch {
+                expected_count: func_def.params.len(),
+                found_count: arguments.len(),
+                begin: exp.src_loc.begin,
+                end: exp.src_loc.end,
+            });
+        }
+
+        // The arguments may read the parameters, so they are all evaluated
+        // before any parameter is overwritten.
+        let mut reg_acc = self.new_reg_acc();
+        let mut obj_acc = self.new_obj_acc();
+        for (i, arg) in arguments.iter_mut().enumerate() {
+            let exp_val = self.eval(arg, &mut reg_acc, &mut obj_acc)?;
+            if exp_val.data_type != func_def.params[i].data_type {
+                return Err(SemanticError::TypeMismatch {
+                    expected_type: func_def.params[i].data_type.clone(),
+                    found_type: exp_val.data_type.clone(),
+                    begin: arg.src_loc.begin,
+                    end: arg.src_loc.end,
+                });
+            }
+            self.mov(&Location::argument(i as u32), &exp_val.location);
+        }
+        let call_args = self.label_call_arguments();
+        self.working_mcfunction().append_commands(vec![
+            "data modify storage memory:stack frame[$(base_index)] merge from storage memory:temp arguments",
+            &format!(
+                "return run function {}:{}-label_0{}",
+                namespace, func_ident.string, call_args
+            ),
+        ]);
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::ast::{exp::Exp, CompileUnit, DataType, GlobalDef, Ident};
+
+use super::{control_flow, ExpVal, Generator, Location, RegAcc, SemanticError};
+
+/// Finds the tasks: the functions that contain `yield` or `sleep`. While a
+/// task waits, its locals stay in its static frame, so it can be neither
+/// recursive nor return a value to the caller, which has gone on already.
+pub(super) fn task_functions(
+    compile_units: &[(CompileUnit, String)],
+    recursive_functions: &HashMap<(String, String), usize>,
+) -> Result<HashSet<(String, String)>, (String, SemanticError)> {
+    let mut task_functions = HashSet::new();
+    for (compile_unit, namespace) in compile_units {
+        for global_def in &compile_unit.global_defs {
+            if let GlobalDef::FuncDef(func_def) = global_def {
+                if !control_flow::block_may_suspend(&func_def.block) {
+                    continue;
+                }
+                let function_id = (namespace.to_owned(), func_def.ident.string.to_owned());
+                let begin = func_def.ident.src_loc.begin;
+                let end = func_def.ident.src_loc.end;
+                if func_def.func_type.is_some() {
+                    return Err((
+                        namespace.to_owned(),
+                        SemanticError::TaskWithReturnValue { begin, end },
+                    ));
+                }
+                if recursive_functions.contains_key(&function_id) {
+                    return Err((
+                        namespace.to_owned(),
+                        SemanticError::RecursiveTask { begin, end },
+                    ));
+                }
+                task_functions.insert(function_id);
+            }
+        }
+    }
+    Ok(task_functions)
+}
+
+/// The register that is 1 while the task waits to go on.
+pub(super) fn running_register(namespace: &str, func_ident: &str) -> String {
+    format!("{}.{}.%running", namespace, func_ident)
+}
+
+impl Generator {
+    /// Compiles `yield;` and `sleep(ticks);`. The rest of the function goes
+    /// to a new label, which a resume label scheduled for later calls. Inside
+    /// loops, `loop_state` 3 makes the loops return, and the resume label
+    /// enters them again once the loops around are generated.
+    pub(super) fn suspend(&mut self, ticks: Option<&mut Exp>) -> Result<(), SemanticError> {
+        let namespace = self.working_namespace_name().to_owned();
+        let running = running_register(&namespace, &self.working_function_ident);
+        let label_following = self.new_label();
+        let mut label_resume = self.new_label();
+        // the resume label is called from outside, like a `pub` function
+        label_resume.append_command("function mcscript:guard");
+        label_resume.append_command(&format!("scoreboard players set {} registers 0", running));
+        if self.source_files.is_some() {
+            label_resume.append_command("scoreboard players set abort registers 0");
+        }
+        if self.loop_depth > 0 {
+            label_resume.append_command("scoreboard players set loop_state registers 0");
+        }
+        label_resume.append_command(&format!(
+            "function {}:{}",
+            namespace,
+            label_following.name()
+        ));
+
+        let resume = format!("{}:{}", namespace, label_resume.name());
+        match ticks {
+            None => self
+                .working_mcfunction()
+                .append_command(&format!("schedule function {} 1t", resume)),
+            Some(ticks) => {
+                if let Some(ticks) = self.const_eval(ticks, &self.scope_namespace)? {
+                    self.working_mcfunction().append_command(&format!(
+                        "schedule function {} {}t",
+                        resume,
+                        ticks.max(1)
+                    ));
+                } else {
+                    let mut reg_acc = self.new_reg_acc();
+                    let exp_val = self.eval(ticks, &mut reg_acc, &mut self.new_obj_acc())?;
+                    if exp_val.data_type != DataType::Int {
+                        return Err(SemanticError::TypeMismatch {
+                            expected_type: DataType::Int,
+                            found_type: exp_val.data_type,
+                            begin: ticks.src_loc.begin,
+                            end: ticks.src_loc.end,
+                        });
+                    }
+                    let reg = self.to_reg_readonly(&exp_val.location, &mut reg_acc);
+                    self.mov(
+                        &Location::Memory("memory:temp".into(), "schedule.ticks".into()),
+                        &reg,
+                    );
+                    self.working_mcfunction().append_commands(vec![
+                        &format!(
+                            "execute if score {} registers matches ..0 run data modify storage memory:temp schedule.ticks set value 1",
+                            reg
+                        ),
+                        &format!(
+                            "data modify storage memory:temp schedule.function set value \"{}\"",
+                            resume
+                        ),
+                        "function mcscript:schedule with storage memory:temp schedule",
+                    ]);
+                }
+            }
+        }
+        let leave = if self.loop_depth > 0 {
+            "return run scoreboard players set loop_state registers 3"
+        } else {
+            "return 0"
+        };
+        self.working_mcfunction().append_commands(vec![
+            &format!("scoreboard players set {} registers 1", running),
+            leave,
+        ]);
+        self.resume_labels.last_mut().unwrap().push(label_resume);
+        self.work_with_next_mcfunction(label_following);
+        Ok(())
+    }
+
+    /// Makes the resume labels of the loop that just ended finish the loop
+    /// and go on with `label_following`, the code after it.
+    pub(super) fn resume_after_loop(&mut self, driver: &str, label_following: &str) {
+        let namespace = self.working_namespace_name().to_owned();
+        let call_args = self.label_call_arguments();
+        let mut resume_labels = self.resume_labels.pop().unwrap();
+        for label_resume in &mut resume_labels {
+            label_resume.append_commands(vec![
+                &format!(
+                    "execute if score loop_state registers matches 0 run function mcscript:loop {{driver: \"{}\"}}",
+                    driver
+                ),
+                "execute unless score loop_state registers matches 1 run return 0",
+                &format!("function {}:{}{}", namespace, label_following, call_args),
+            ]);
+        }
+        self.resume_labels.last_mut().unwrap().extend(resume_labels);
+    }
+
+    /// Compiles `running(f)`.
+    pub(super) fn eval_running(
+        &mut self,
+        namespace: &Option<Ident>,
+        func_ident: &Ident,
+        reg_acc: &mut RegAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        let namespace = match namespace {
+            Some(namespace) => namespace.string.to_owned(),
+            None => self.scope_namespace.clone(),
+        };
+        self.function_table.query_function(&namespace, func_ident)?;
+        if !self
+            .task_functions
+            .contains(&(namespace.clone(), func_ident.string.clone()))
+        {
+            return Err(SemanticError::NotATask {
+                ident: func_ident.string.to_owned(),
+                begin: func_ident.src_loc.begin,
+                end: func_ident.src_loc.end,
+            });
+        }
+        let reg = reg_acc.new_reg();
+        self.working_mcfunction().append_command(&format!(
+            "execute store success score {} registers if score {} registers matches 1",
+            reg,
+            running_register(&namespace, &func_ident.string)
+        ));
+        Ok(ExpVal {
+            data_type: DataType::Int,
+            location: reg,
+        })
+    }
+}
@@ -0,0 +1,222 @@
+use crate::frontend::ast::{
+    exp::{Exp, ExpType},
+    DataType, SrcLocation,
+};
+
+use super::{
+    builtins::string_argument, ExpVal, Generator, Location, ObjAcc, RegAcc, SemanticError,
+};
+
+const COLORS: [&str; 16] = [
+    "black",
+    "dark_blue",
+    "dark_green",
+    "dark_aqua",
+    "dark_red",
+    "dark_purple",
+    "gold",
+    "gray",
+    "dark_gray",
+    "blue",
+    "green",
+    "aqua",
+    "red",
+    "light_purple",
+    "yellow",
+    "white",
+];
+
+const FORMATS: [&str; 5] = [
+    "bold",
+    "italic",
+    "underlined",
+    "strikethrough",
+    "obfuscated",
+];
+
+/// `tellraw`, `title`, `subtitle`, `actionbar` and `bossbar_name` as the
+/// command that shows the text, with `{}` for the target.
+pub(super) fn text_builtin(ident: &str) -> Option<&'static str> {
+    match ident {
+        "tellraw" => Some("tellraw {}"),
+        "title" => Some("title {} title"),
+        "subtitle" => Some("title {} subtitle"),
+        "actionbar" => Some("title {} actionbar"),
+        "bossbar_name" => Some("bossbar set {} name"),
+        _ => None,
+    }
+}
+
+fn json_string(string: &str) -> String {
+    let mut json = String::from('"');
+    for c in string.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Turns `style`, e.g. `"aqua,bold"` or `"#ff8000,italic"`, into the keys of
+/// a text component.
+fn parse_style(style: &str) -> Result<Vec<String>, String> {
+    let mut keys = vec![];
+    for item in style.split(',').map(str::trim) {
+        if COLORS.contains(&item)
+            || (item.len() == 7
+                && item.starts_with('#')
+                && item[1..].chars().all(|c| c.is_ascii_hexdigit()))
+        {
+            keys.push(format!("\"color\": \"{}\"", item));
+        } else if FORMATS.contains(&item) {
+            keys.push(format!("\"{}\": true", item));
+        } else {
+            return Err(format!(
+                "unknown text style \"{}\", expected a color or one of bold, italic, underlined, strikethrough and obfuscated",
+                item
+            ));
+        }
+    }
+    Ok(keys)
+}
+
+impl Generator {
+    /// Compiles `tellraw(targets, parts...)` and the like. Each part is a
+    /// string literal, a selector, a value, or `styled("aqua,bold", parts...)`.
+    /// Values are read by `score` and `nbt` components when the text is
+    /// shown, so they need no macro.
+    pub(super) fn text_call(
+        &mut self,
+        command: &str,
+        arguments: &mut [Box<Exp>],
+        src_loc: &SrcLocation,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<ExpVal, SemanticError> {
+        if arguments.len() < 2 {
+            return Err(SemanticError::FuncArgumentsCountMismatch {
+                expected_count: 2,
+                found_count: arguments.len(),
+                begin: src_loc.begin,
+                end: src_loc.end,
+            });
+        }
+        let mut macro_arguments = vec![];
+        let target = if command.starts_with("bossbar") {
+            string_argument(&arguments[0])?
+        } else {
+            self.selector_argument(&mut arguments[0], &mut macro_arguments, reg_acc, obj_acc)?
+        };
+        let mut components = vec![];
+        for part in arguments[1..].iter_mut() {
+            components.push(self.text_component(part, &mut macro_arguments, reg_acc, obj_acc)?);
+        }
+        let text = if components.len() == 1 {
+            components.pop().unwrap()
+        } else {
+            format!("[\"\", {}]", components.join(", "))
+        };
+        self.append_macro_command(
+            &format!("{} {}", command.replace("{}", &target), text),
+            &macro_arguments,
+        );
+        Ok(ExpVal {
+            data_type: DataType::Int,
+            location: Location::return_value(),
+        })
+    }
+
+    /// The JSON text component of `part`. The keys are separated by spaces,
+    /// so that the optimizer sees the registers read by `score` components.
+    fn text_component(
+        &mut self,
+        part: &mut Exp,
+        macro_arguments: &mut Vec<Location>,
+        reg_acc: &mut RegAcc,
+        obj_acc: &mut ObjAcc,
+    ) -> Result<String, SemanticError> {
+        match &mut part.exp_type {
+            ExpType::StringLiteral(text) => return Ok(json_string(text)),
+            ExpType::Selector(selector) => {
+                return Ok(format!("{{\"selector\": {}}}", json_string(selector)))
+            }
+            ExpType::FuncCall {
+                namespace: None,
+                func_ident,
+                arguments,
+            } if func_ident.string == "styled"
+                && self
+                    .function_table
+                    .query_function(&self.scope_namespace, func_ident)
+                    .is_err() =>
+            {
+                if arguments.len() < 2 {
+                    return Err(SemanticError::FuncArgumentsCountMismatch {
+                        expected_count: 2,
+                        found_count: arguments.len(),
+                        begin: part.src_loc.begin,
+                        end: part.src_loc.end,
+                    });
+                }
+                let style = &arguments[0];
+                let mut keys = parse_style(&string_argument(style)?).map_err(|message| {
+                    SemanticError::InvalidTextComponent {
+                        message,
+                        begin: style.src_loc.begin,
+                        end: style.src_loc.end,
+                    }
+                })?;
+                let mut extra = vec![];
+                for part in arguments[1..].iter_mut() {
+                    extra.push(self.text_component(part, macro_arguments, reg_acc, obj_acc)?);
+                }
+                keys.push(format!("\"extra\": [{}]", extra.join(", ")));
+                return Ok(format!("{{\"text\": \"\", {}}}", keys.join(", ")));
+            }
+            _ => {}
+        }
+
+        if let Some(value) = self.const_eval(part, &self.scope_namespace)? {
+            return Ok(json_string(&value.to_string()));
+        }
+        let exp_val = self.eval(part, reg_acc, obj_acc)?;
+        if exp_val.data_type == DataType::Selector {
+            macro_arguments.push(exp_val.location);
+            return Ok(format!(
+                "{{\"selector\": \"$({})\"}}",
+                macro_arguments.len() - 1
+            ));
+        }
+        let location = match exp_val.location {
+            Location::MemoryRef(..) => {
+                let obj = obj_acc.new_obj(&self.frame);
+                self.mov(&obj, &exp_val.location);
+                obj
+            }
+            location => location,
+        };
+        Ok(match location {
+            Location::Register(reg) => score_component(&reg, "registers"),
+            Location::Score(holder, objective) => score_component(&holder, &objective),
+            Location::Memory(naid, path) => format!(
+                "{{\"storage\": {}, \"nbt\": {}}}",
+                json_string(&naid),
+                json_string(&path)
+            ),
+            Location::MemoryRef(..) => unreachable!(),
+        })
+    }
+}
+
+fn score_component(holder: &str, objective: &str) -> String {
+    format!(
+        "{{\"score\": {{\"name\": {}, \"objective\": {}}}}}",
+        json_string(holder),
+        json_string(objective)
+    )
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet};
+
+use super::datapack::{macro_line, referenced_functions, Datapack, Mcfunction};
+
+pub struct OptimizationStats {
+    pub commands_before: usize,
+    pub commands_after: usize,
+}
+
+/// Runs the peephole passes over every function of `datapack` until none of
+/// them changes anything.
+pub fn optimize(datapack: &mut Datapack) -> OptimizationStats {
+    let commands_before = datapack.command_count();
+    loop {
+        let mut changed = false;
+        for namespace in datapack.namespaces_mut() {
+            for mcfunction in namespace.mcfunctions_mut() {
+                changed |= truncate_after_return(mcfunction);
+                changed |= forward_moves(mcfunction);
+            }
+        }
+        changed |= eliminate_dead_register_writes(datapack);
+        if !changed {
+            break;
+        }
+    }
+    OptimizationStats {
+        commands_before,
+        commands_after: datapack.command_count(),
+    }
+}
+
+/// Temporary registers (`r0`, `r1`, ...) are only alive within a single
+/// statement, so unlike `base_index` they can be analysed.
+fn is_temp_register(holder: &str) -> bool {
+    holder.len() > 1 && holder.starts_with('r') && holder[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+enum Move<'a> {
+    /// `scoreboard players set <reg> registers <value>`
+    SetImmediate { reg: &'a str, value: &'a str },
+    /// `scoreboard players operation <dest> registers = <src> registers`
+    RegToReg { dest: &'a str, src: &'a str },
+    /// `execute store result storage <storage> int 1.0 run scoreboard players get <reg> registers`
+    RegToMemory { storage: String, reg: &'a str },
+    /// `execute store result score <reg> registers run data get storage <storage> 1.0`
+    MemoryToReg { reg: &'a str, storage: String },
+}
+
+fn parse_move(line: &str) -> Option<Move<'_>> {
+    let tokens = line.trim_start_matches('$').split(' ').collect::<Vec<_>>();
+    match tokens.as_slice() {
+        ["scoreboard", "players", "set", reg, "registers", value] => {
+            Some(Move::SetImmediate { reg, value })
+        }
+        ["scoreboard", "players", "operation", dest, "registers", "=", src, "registers"] => {
+            Some(Move::RegToReg { dest, src })
+        }
+        ["execute", "store", "result", "storage", naid, path, "int", "1.0", "run", "scoreboard", "players", "get", reg, "registers"] => {
+            Some(Move::RegToMemory {
+                storage: format!("{} {}", naid, path),
+                reg,
+            })
+        }
+        ["execute", "store", "result", "score", reg, "registers", "run", "data", "get", "storage", naid, path, "1.0"] => {
+            Some(Move::MemoryToReg {
+                reg,
+                storage: format!("{} {}", naid, path),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Removes the lines after the first `return`, which are never run.
+fn truncate_after_return(mcfunction: &mut Mcfunction) -> bool {
+    let commands = mcfunction.commands_mut();
+    match commands
+        .iter()
+        .position(|line| line.trim_start_matches('$').starts_with("return "))
+    {
+        Some(i) if i + 1 < commands.len() => {
+            commands.truncate(i + 1);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Rewrites pairs of adjacent moves where the second one only copies back a
+/// value that is already known.
+fn forward_moves(mcfunction: &mut Mcfunction) -> bool {
+    let commands = mcfunction.commands_mut();
+    let mut changed = false;
+    let mut i = 0;
+    while i < commands.len() {
+        if let Some(Move::RegToReg { dest, src }) = parse_move(&commands[i]) {
+            if dest == src {
+                commands.remove(i);
+                changed = true;
+                continue;
+            }
+        }
+        let Some(j) = (i + 1..commands.len()).find(|j| !commands[*j].is_empty()) else {
+            break;
+        };
+        let replacement = match (parse_move(&commands[i]), parse_move(&commands[j])) {
+            // a store followed by a load of the same value
+            (
+                Some(Move::RegToMemory { storage, reg: src }),
+                Some(Move::MemoryToReg {
+                    reg: dest,
+                    storage: storage_1,
+                }),
+            ) if storage == storage_1 => Some(Some(format!(
+                "scoreboard players operation {} registers = {} registers",
+                dest, src
+            ))),
+            // a load followed by storing the value back
+            (
+                Some(Move::MemoryToReg { reg, storage }),
+                Some(Move::RegToMemory {
+                    storage: storage_1,
+                    reg: reg_1,
+                }),
+            ) if storage == storage_1 && reg == reg_1 => Some(None),
+            // moving an immediate through a register
+            (
+                Some(Move::SetImmediate { reg, value }),
+                Some(Move::RegToMemory {
+                    storage,
+                    reg: reg_1,
+                }),
+            ) if reg == reg_1 => Some(Some(format!(
+                "data modify storage {} set value {}",
+                storage, value
+            ))),
+            (Some(Move::SetImmediate { reg, value }), Some(Move::RegToReg { dest, src }))
+                if reg == src =>
+            {
+                Some(Some(format!(
+                    "scoreboard players set {} registers {}",
+                    dest, value
+                )))
+            }
+            _ => None,
+        };
+        match replacement {
+            Some(Some(command)) => {
+                commands[j] = macro_line(&command);
+                changed = true;
+            }
+            Some(None) => {
+                commands.remove(j);
+                changed = true;
+            }
+            None => i += 1,
+        }
+    }
+    changed
+}
+
+struct Effect {
+    /// The temporary register that the command overwrites without reading it.
+    def: Option<String>,
+    uses: Vec<String>,
+    /// The functions the command calls, and whether each call is in tail
+    /// position, i.e. `return run function ...`.
+    callees: Vec<(String, bool)>,
+    returns: bool,
+    /// Whether the command returns only if a condition holds, e.g.
+    /// `execute if ... run return 0`.
+    may_return: bool,
+}
+
+fn effect(line: &str, mcfunction_ids: &HashSet<String>) -> Effect {
+    let (def, uses) = match parse_move(line) {
+        Some(Move::SetImmediate { reg, .. }) | Some(Move::MemoryToReg { reg, .. })
+            if is_temp_register(reg) =>
+        {
+            (Some(reg.to_owned()), vec![])
+        }
+        Some(Move::RegToReg { dest, src }) if is_temp_register(dest) && dest != src => {
+            (Some(dest.to_owned()), vec![src.to_owned()])
+        }
+        _ => {
+            // Any other mention of a register, e.g. `"r3"` passed to
+            // `mcscript:mov_m_r`, is conservatively treated as a read.
+            let uses = line
+                .split(' ')
+                .map(|token| token.trim_matches(|c: char| !c.is_ascii_alphanumeric()))
+                .filter(|token| is_temp_register(token))
+                .map(|token| token.to_owned())
+                .collect();
+            (None, uses)
+        }
+    };
+    let callees = referenced_functions(line)
+        .filter(|token| mcfunction_ids.contains(*token))
+        .map(|token| {
+            let tail = line.contains(&format!("return run function {}", token));
+            (token.to_owned(), tail)
+        })
+        .collect();
+    let returns = line.trim_start_matches('$').starts_with("return ");
+    let may_return = !returns && line.contains(" run return ");
+    Effect {
+        def,
+        uses,
+        callees,
+        returns,
+        may_return,
+    }
+}
+
+/// Registers alive at the beginning and at the end of every function.
+#[derive(Default, PartialEq)]
+struct Liveness {
+    live_in: HashMap<String, HashSet<String>>,
+    live_out: HashMap<String, HashSet<String>>,
+}
+
+/// Computes the registers that are alive before each line of `effects`, the
+/// lines of function `id`. Returns the registers alive at its beginning and
+/// the lines that write a register nobody reads, and adds the registers alive
+/// after each call to the end of the callee in `callee_live_out`.
+fn liveness(
+    id: &str,
+    effects: &[Effect],
+    known: &Liveness,
+    callee_live_out: &mut HashMap<String, HashSet<String>>,
+) -> (HashSet<String>, Vec<usize>) {
+    let empty = HashSet::new();
+    let live_out = known.live_out.get(id).unwrap_or(&empty);
+    let mut live = live_out.clone();
+    let mut dead_lines = vec![];
+    for (i, effect) in effects.iter().enumerate().rev() {
+        if effect.returns {
+            // `return` leaves this function, and the caller goes on.
+            live = live_out.clone();
+        }
+        if effect.may_return {
+            live.extend(live_out.iter().cloned());
+        }
+        let live_after = live.clone();
+        if let Some(def) = &effect.def {
+            if !live.contains(def) {
+                dead_lines.push(i);
+                continue;
+            }
+            live.remove(def);
+        }
+        live.extend(effect.uses.iter().cloned());
+        for (callee, tail) in &effect.callees {
+            callee_live_out
+                .entry(callee.to_owned())
+                .or_default()
+                .extend(if *tail { live_out } else { &live_after }.iter().cloned());
+            if let Some(callee_live_in) = known.live_in.get(callee) {
+                live.extend(callee_live_in.iter().cloned());
+            }
+        }
+    }
+    (live, dead_lines)
+}
+
+/// Removes writes to temporary registers that are never read afterwards.
+///
+/// Control flow in the generated code only goes forward through calls to
+/// label functions, so a register is alive after a call if the callee may
+/// read it before writing it, and alive at the end of a function if it is
+/// alive after some call to that function.
+fn eliminate_dead_register_writes(datapack: &mut Datapack) -> bool {
+    let mut mcfunction_ids = HashSet::new();
+    for namespace in datapack.namespaces() {
+        for mcfunction in namespace.mcfunctions() {
+            mcfunction_ids.insert(format!("{}:{}", namespace.name(), mcfunction.name()));
+        }
+    }
+    let mut effects = HashMap::new();
+    for namespace in datapack.namespaces() {
+        for mcfunction in namespace.mcfunctions() {
+            effects.insert(
+                format!("{}:{}", namespace.name(), mcfunction.name()),
+                mcfunction
+                    .commands()
+                    .iter()
+                    .map(|line| effect(line, &mcfunction_ids))
+                    .collect::<Vec<_>>(),
+            );
+        }
+    }
+
+    let mut known = Liveness::default();
+    loop {
+        let mut next = Liveness::default();
+        for (id, effects) in &effects {
+            let (live, _) = liveness(id, effects, &known, &mut next.live_out);
+            next.live_in.insert(id.clone(), live);
+        }
+        next.live_out.retain(|_, live| !live.is_empty());
+        next.live_in.retain(|_, live| !live.is_empty());
+        if next == known {
+            break;
+        }
+        known = next;
+    }
+
+    let mut changed = false;
+    for namespace in datapack.namespaces_mut() {
+        let namespace_name = namespace.name().to_owned();
+        for mcfunction in namespace.mcfunctions_mut() {
+            let id = format!("{}:{}", namespace_name, mcfunction.name());
+            let (_, dead_lines) = liveness(&id, &effects[&id], &known, &mut HashMap::new());
+            // `dead_lines` is in descending order
+            for i in dead_lines {
+                mcfunction.commands_mut().remove(i);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
@@ -4,12 +4,15 @@ use std::{
 };
 
 use crossterm::{
-    style::{Print, PrintStyledContent, Stylize},
+    style::{Color, Print, PrintStyledContent, Stylize},
     ExecutableCommand,
 };
 use lalrpop_util::{lexer::Token, ParseError};
 
-use crate::{backend::error::SemanticError, frontend::ast::exp::ArrayMethodType};
+use crate::{
+    backend::error::{SemanticError, Warning},
+    frontend::{ast::exp::ArrayMethodType, lexer::LexicalError},
+};
 
 pub struct Split<'a>(Vec<(&'a str, usize, usize)>);
 
@@ -47,6 +50,36 @@ pub fn show_error_message(
     begin: usize,
     end: usize,
     message: &str,
+) -> Result<()> {
+    show_message(file_path, content, begin, end, "error", Color::Red, message)
+}
+
+pub fn show_warning_message(
+    file_path: &Path,
+    content: &Split,
+    begin: usize,
+    end: usize,
+    message: &str,
+) -> Result<()> {
+    show_message(
+        file_path,
+        content,
+        begin,
+        end,
+        "warning",
+        Color::Yellow,
+        message,
+    )
+}
+
+fn show_message(
+    file_path: &Path,
+    content: &Split,
+    begin: usize,
+    end: usize,
+    level: &str,
+    color: Color,
+    message: &str,
 ) -> Result<()> {
     let (line_num, line, begin, end) = content.query_loc(begin, end);
     let line_num = line_num + 1;
@@ -65,7 +98,7 @@ pub fn show_error_message(
     }
 
     stdout()
-        .execute(PrintStyledContent("error".red().bold()))?
+        .execute(PrintStyledContent(level.with(color).bold()))?
         .execute(Print(": "))?
         .execute(Print(message))?
         .execute(Print("\n"))?
@@ -86,7 +119,7 @@ pub fn show_error_message(
         .execute(Print("\n"))?
         .execute(Print(space))?
         .execute(PrintStyledContent(" |  ".grey()))?
-        .execute(PrintStyledContent(underline.red()))?
+        .execute(PrintStyledContent(underline.with(color)))?
         .execute(Print("\n"))?
         .execute(Print("\n"))?;
 
@@ -96,7 +129,7 @@ pub fn show_error_message(
 pub fn handle_parse_error(
     file_path: &Path,
     content: &str,
-    err: &ParseError<usize, Token, &str>,
+    err: &ParseError<usize, Token, LexicalError>,
 ) -> Result<()> {
     let content = Split::new(content);
     match err {
@@ -146,7 +179,7 @@ pub fn handle_parse_error(
             )?;
         }
         ParseError::User { error } => {
-            println!("{}", error);
+            show_error_message(file_path, &content, error.begin, error.end, error.message)?;
         }
     }
     Ok(())
@@ -298,6 +331,232 @@ pub fn handle_semantic_error(file_path: &Path, content: &str, err: &SemanticErro
                 ),
             )?;
         }
+        SemanticError::NonConstantExpression { begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                "expected a constant expression of type \"int\" here",
+            )?;
+        }
+        SemanticError::AssignToConstant { ident, begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!("cannot assign to constant \"{}\"", ident),
+            )?;
+        }
+        SemanticError::UnknownAttribute {
+            attribute,
+            begin,
+            end,
+        } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!("unknown attribute \"{}\"", attribute),
+            )?;
+        }
+        SemanticError::TaggedFunctionWithParameters {
+            attribute,
+            begin,
+            end,
+        } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!(
+                    "functions marked with \"#[{}]\" cannot have parameters",
+                    attribute
+                ),
+            )?;
+        }
+        SemanticError::TaskWithReturnValue { begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                "functions containing \"yield\" or \"sleep\" cannot return a value",
+            )?;
+        }
+        SemanticError::RecursiveTask { begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                "functions containing \"yield\" or \"sleep\" cannot be recursive",
+            )?;
+        }
+        SemanticError::NotATask { ident, begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!(
+                    "\"{}\" is not a task, i.e. it contains no \"yield\" or \"sleep\"",
+                    ident
+                ),
+            )?;
+        }
+        SemanticError::DivisionByZero { begin, end } => {
+            show_error_message(file_path, &content_split, *begin, *end, "division by zero")?;
+        }
+        SemanticError::UnknownExecuteModifier {
+            modifier,
+            begin,
+            end,
+        } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!("\"{}\" is not a subcommand of \"execute\"", modifier),
+            )?;
+        }
+        SemanticError::InvalidSelector {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidScoreboardBinding {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::UnexpectedStringLiteral { begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                "string literals can only be passed to builtin functions",
+            )?;
+        }
+        SemanticError::ExpectedStringLiteral { begin, end } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                "expected a string literal",
+            )?;
+        }
+        SemanticError::InvalidBlock {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::UnexpectedAttributeArguments {
+            attribute,
+            begin,
+            end,
+        } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!("attribute \"#[{}]\" takes no arguments", attribute),
+            )?;
+        }
+        SemanticError::InvalidEventHandler {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidResource {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidCommand {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidFormatString {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidTextComponent {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::InvalidNbtPath {
+            message,
+            begin,
+            end,
+        } => {
+            show_error_message(file_path, &content_split, *begin, *end, message)?;
+        }
+        SemanticError::NotAllowedInExecuteBlock {
+            statement,
+            begin,
+            end,
+        } => {
+            show_error_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!("\"{}\" is not allowed in an execute block", statement),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn handle_warning(file_path: &Path, content: &str, warning: &Warning) -> Result<()> {
+    let content_split = Split::new(content);
+    match warning {
+        Warning::UnreachableCode { begin, end } => {
+            show_warning_message(file_path, &content_split, *begin, *end, "unreachable code")?;
+        }
+        Warning::UnusedFunction {
+            function,
+            begin,
+            end,
+        } => {
+            show_warning_message(
+                file_path,
+                &content_split,
+                *begin,
+                *end,
+                &format!(
+                    "function `{}` is never called and is left out of the datapack; make it `pub` to call it from commands",
+                    function
+                ),
+            )?;
+        }
     }
     Ok(())
 }
@@ -3,3 +3,4 @@ lalrpop_mod!(pub parser);
 
 pub mod ast;
 pub mod lexer;
+pub mod selector;
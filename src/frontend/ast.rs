@@ -1,4 +1,4 @@
-use std::fmt::Display;
+use std::{fmt::Display, path::Path};
 
 use exp::Exp;
 
@@ -18,21 +18,99 @@ pub struct CompileUnit {
 #[derive(Debug)]
 pub enum GlobalDef {
     FuncDef(FuncDef),
+    ExternFuncDef(ExternFuncDef),
     VariableDef {
         ident: Ident,
         init_value: Box<Exp>,
         data_type: DataType,
     },
+    ConstDef {
+        ident: Ident,
+        value: Box<Exp>,
+    },
+    /// `scoreboard ident: int = "objective" for "holder";`, an `int` kept in
+    /// the score of `holder` for `objective`.
+    ScoreboardDef {
+        ident: Ident,
+        objective: String,
+        holder: String,
+        src_loc: SrcLocation,
+    },
+    ResourceDef(ResourceDef),
+}
+
+impl CompileUnit {
+    /// Makes the paths of `include_json!` relative to `dir`, the directory of
+    /// the source file.
+    pub fn resolve_include_paths(&mut self, dir: &Path) {
+        for global_def in &mut self.global_defs {
+            if let GlobalDef::ResourceDef(ResourceDef {
+                json: ResourceJson::Include(path),
+                ..
+            }) = global_def
+            {
+                *path = dir.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct FuncDef {
+    pub attributes: Vec<Attribute>,
+    /// Whether the function is declared with `pub`, i.e. it may be called
+    /// from outside the datapack.
+    pub public: bool,
     pub ident: Ident,
     pub params: Vec<FuncParam>,
     pub block: Block,
     pub func_type: Option<DataType>,
 }
 
+/// `extern fn ns:path/to_func(...) -> type;`, a function of another datapack.
+/// It is called by `ident`, the last segment of its `path`.
+#[derive(Debug, Clone)]
+pub struct ExternFuncDef {
+    pub path: String,
+    pub ident: Ident,
+    pub params: Vec<FuncParam>,
+    pub func_type: Option<DataType>,
+}
+
+/// `resource advancement "path/name" = include_json!("file.json");`, a JSON
+/// file written to `data/<namespace>/advancement/path/name.json`.
+#[derive(Debug, Clone)]
+pub struct ResourceDef {
+    /// The directory of the resource, e.g. `loot_table` or `tags/block`.
+    pub kind: Ident,
+    pub name: String,
+    pub json: ResourceJson,
+    pub src_loc: SrcLocation,
+}
+
+#[derive(Debug, Clone)]
+pub enum ResourceJson {
+    Inline(String),
+    /// `include_json!("file.json")`, a file next to the source file.
+    Include(String),
+}
+
+/// A subcommand of `execute` before a block, e.g. `as "@e[tag=x]"`.
+#[derive(Debug, Clone)]
+pub struct ExecuteModifier {
+    pub ident: Ident,
+    /// A string literal, pasted as it is, or a `Selector`.
+    pub argument: Box<Exp>,
+}
+
+/// `#[ident]` or `#[ident(arguments...)]` before a function definition.
+#[derive(Debug, Clone)]
+pub struct Attribute {
+    pub ident: Ident,
+    /// Identifiers, as `ExpType::Variable`, and string literals.
+    pub arguments: Vec<Box<Exp>>,
+}
+
 #[derive(Debug, Clone)]
 pub struct FuncParam {
     pub ident: Ident,
@@ -49,6 +127,7 @@ pub struct Ident {
 pub enum DataType {
     Int,
     Array { element_type: Box<DataType> },
+    Selector,
 }
 
 impl Display for DataType {
@@ -58,6 +137,7 @@ impl Display for DataType {
             DataType::Array { element_type } => {
                 write!(f, "Array<{}>", element_type)
             }
+            DataType::Selector => write!(f, "Selector"),
         }
     }
 }
@@ -103,10 +183,27 @@ pub enum Stmt {
     Continue {
         src_loc: SrcLocation,
     },
+    /// `yield;`, which goes on in the next tick.
+    Yield {
+        src_loc: SrcLocation,
+    },
+    /// `sleep(ticks);`
+    Sleep {
+        ticks: Box<Exp>,
+        src_loc: SrcLocation,
+    },
+    /// `execute as "@e" at "@s" { ... }`, which runs `body` in each of the
+    /// contexts the subcommands make.
+    Execute {
+        modifiers: Vec<ExecuteModifier>,
+        body: Block,
+        src_loc: SrcLocation,
+    },
     Exp(Box<Exp>),
     InlineCommand {
         is_mod_command: bool,
         fmt_str: String,
         arguments: Vec<Box<Exp>>,
+        src_loc: SrcLocation,
     },
 }
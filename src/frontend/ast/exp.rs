@@ -36,6 +36,23 @@ pub enum ExpType {
         element_type: Option<DataType>,
         elements: Vec<Box<Exp>>,
     },
+    /// Only allowed as an argument of a builtin function, or of a subcommand
+    /// of an execute block.
+    StringLiteral(String),
+    /// A selector literal, e.g. `@e[tag=foo]`.
+    Selector(String),
+    /// `selector.with("tag=r{}c{}", r, c)`, the selector with one more
+    /// argument, whose `{}`s are replaced by the values of `arguments`.
+    SelectorWith {
+        selector: Box<Exp>,
+        argument: String,
+        arguments: Vec<Box<Exp>>,
+    },
+    /// `running(f)`, whether the task `f` is waiting to go on.
+    Running {
+        namespace: Option<Ident>,
+        func_ident: Ident,
+    },
 }
 
 #[derive(Debug, Clone)]
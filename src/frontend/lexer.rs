@@ -1,3 +1,45 @@
+use lalrpop_util::ParseError;
+
+use crate::frontend::selector::validate_selector;
+
 pub fn apply_string_escapes(src: &str) -> String {
     src.replace("\\\"", "\"").replace("\\\\", "\\")
 }
+
+/// An error the parser reports on top of the syntax errors.
+pub struct LexicalError {
+    pub message: &'static str,
+    pub begin: usize,
+    pub end: usize,
+}
+
+pub fn parse_int_const<T>(
+    src: &str,
+    radix: u32,
+    begin: usize,
+    end: usize,
+) -> Result<i32, ParseError<usize, T, LexicalError>> {
+    i32::from_str_radix(src, radix).map_err(|_| ParseError::User {
+        error: LexicalError {
+            message: "integer literal out of range",
+            begin,
+            end,
+        },
+    })
+}
+
+pub fn parse_selector<T>(
+    src: &str,
+    begin: usize,
+    end: usize,
+) -> Result<String, ParseError<usize, T, LexicalError>> {
+    validate_selector(src)
+        .map(|_| src.to_owned())
+        .map_err(|message| ParseError::User {
+            error: LexicalError {
+                message,
+                begin,
+                end,
+            },
+        })
+}
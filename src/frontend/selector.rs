@@ -0,0 +1,122 @@
+//! Checks target selectors like `@e[tag=foo,limit=1]` at compile time, as far
+//! as they can be checked without knowing the world.
+
+const VARIABLES: [char; 6] = ['a', 'e', 'n', 'p', 'r', 's'];
+
+const ARGUMENTS: [&str; 21] = [
+    "advancements",
+    "distance",
+    "dx",
+    "dy",
+    "dz",
+    "gamemode",
+    "level",
+    "limit",
+    "name",
+    "nbt",
+    "predicate",
+    "scores",
+    "sort",
+    "tag",
+    "team",
+    "type",
+    "x",
+    "x_rotation",
+    "y",
+    "y_rotation",
+    "z",
+];
+
+/// Checks a whole selector, e.g. `@e[tag=foo]`.
+pub fn validate_selector(src: &str) -> Result<(), &'static str> {
+    let mut chars = src.chars();
+    if chars.next() != Some('@') {
+        return Err("a selector must start with \"@\"");
+    }
+    match chars.next() {
+        Some(variable) if VARIABLES.contains(&variable) => {}
+        _ => return Err("unknown selector variable, expected one of @a, @e, @n, @p, @r and @s"),
+    }
+    let arguments = chars.as_str();
+    if arguments.is_empty() {
+        return Ok(());
+    }
+    if !arguments.starts_with('[') || !arguments.ends_with(']') {
+        return Err("the arguments of a selector must be in square brackets");
+    }
+    let arguments = &arguments[1..arguments.len() - 1];
+    if arguments.trim().is_empty() {
+        return Ok(());
+    }
+    for argument in split_arguments(arguments)? {
+        validate_selector_argument(argument)?;
+    }
+    Ok(())
+}
+
+/// Checks one `key=value` argument of a selector. `{}` stands for a value
+/// only known at runtime.
+pub fn validate_selector_argument(src: &str) -> Result<(), &'static str> {
+    if src.contains('"') {
+        return Err("selectors cannot contain '\"', quote strings with single quotes");
+    }
+    let Some((key, value)) = src.split_once('=') else {
+        return Err("a selector argument must look like \"key=value\"");
+    };
+    let key = key.trim();
+    if !ARGUMENTS.contains(&key) {
+        return Err("unknown selector argument");
+    }
+    let value = value.trim();
+    if value.contains("{}") {
+        return Ok(());
+    }
+    let negated = value.strip_prefix('!');
+    match key {
+        "limit" => match value.parse::<i32>() {
+            Ok(limit) if limit > 0 => Ok(()),
+            _ => Err("\"limit\" must be a positive integer"),
+        },
+        "sort" => match value {
+            "nearest" | "furthest" | "random" | "arbitrary" => Ok(()),
+            _ => Err("\"sort\" must be one of nearest, furthest, random and arbitrary"),
+        },
+        "gamemode" => match negated.unwrap_or(value) {
+            "survival" | "creative" | "adventure" | "spectator" => Ok(()),
+            _ => Err("\"gamemode\" must be one of survival, creative, adventure and spectator"),
+        },
+        "tag" | "team" => Ok(()),
+        _ if value.is_empty() => Err("missing value of selector argument"),
+        _ => Ok(()),
+    }
+}
+
+/// Splits `arguments` at the commas outside of brackets, braces and quotes.
+fn split_arguments(arguments: &str) -> Result<Vec<&str>, &'static str> {
+    let mut result = vec![];
+    let mut depth = 0;
+    let mut quoted = false;
+    let mut begin = 0;
+    for (i, c) in arguments.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '[' | '{' if !quoted => depth += 1,
+            ']' | '}' if !quoted => {
+                if depth == 0 {
+                    return Err("unbalanced brackets in selector");
+                }
+                depth -= 1;
+            }
+            ',' if !quoted && depth == 0 => {
+                result.push(&arguments[begin..i]);
+                begin = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 || quoted {
+        return Err("unbalanced brackets in selector");
+    }
+    result.push(&arguments[begin..]);
+    Ok(result)
+}
@@ -10,8 +10,13 @@ use crossterm::{
     ExecutableCommand,
 };
 use mcsc::{
-    backend::{datapack, generator::Generator},
-    error::{handle_parse_error, handle_semantic_error},
+    backend::{
+        command_tree::CommandTree,
+        datapack,
+        generator::{Generator, SourceFile},
+        optimizer,
+    },
+    error::{handle_parse_error, handle_semantic_error, handle_warning},
     frontend::{self},
 };
 
@@ -26,10 +31,52 @@ struct CLI {
     #[arg(short)]
     output_dir: String,
 
+    /// Print how many commands the optimiser removed
+    #[arg(long)]
+    stats: bool,
+
+    /// Check array subscripts, pop/erase on empty arrays and division by
+    /// zero at runtime, and report failures in the chat. A failed check
+    /// aborts the call: every function returns and the stack is reset
+    #[arg(long)]
+    checked: bool,
+
+    /// Target Minecraft version, 1.20.2 or later. Since 1.20.3, functions
+    /// return `int` values with `return` instead of in command storage
+    #[arg(long, default_value = "1.21", value_parser = parse_mc_version)]
+    mc_version: (u32, u32, u32),
+
+    /// Read the blocks accepted by `set_block`, `fill` and `is_block` from a
+    /// file, one block ID or `#`-prefixed block tag per line, instead of
+    /// using the vanilla ones
+    #[arg(long)]
+    block_registry: Option<String>,
+
+    /// Check the generated commands against a `commands.json` report of the
+    /// Minecraft data generator
+    #[arg(long)]
+    commands_report: Option<String>,
+
     /// Input source files
     files: Vec<String>,
 }
 
+fn parse_mc_version(version: &str) -> std::result::Result<(u32, u32, u32), String> {
+    let numbers = version
+        .split('.')
+        .map(|number| number.parse())
+        .collect::<std::result::Result<Vec<u32>, _>>();
+    let version = match numbers.as_deref() {
+        Ok([major, minor]) => (*major, *minor, 0),
+        Ok([major, minor, patch]) => (*major, *minor, *patch),
+        _ => return Err(format!("invalid version \"{}\"", version)),
+    };
+    if version < (1, 20, 2) {
+        return Err("the generated functions use macros, which need Minecraft 1.20.2 or later".into());
+    }
+    Ok(version)
+}
+
 fn main() -> Result<()> {
     let cli = CLI::parse();
 
@@ -56,7 +103,8 @@ fn main() -> Result<()> {
         );
         let parse_res = frontend::parser::CompileUnitParser::new().parse(&input);
         match parse_res {
-            Ok(ast) => {
+            Ok(mut ast) => {
+                ast.resolve_include_paths(path.parent().unwrap_or(Path::new("")));
                 compile_units.push((ast, file_name_no_extension));
             }
             Err(err) => {
@@ -65,16 +113,69 @@ fn main() -> Result<()> {
         }
     }
     let output = cli.output_dir;
-    let generate_result = Generator::new(output.clone()).generate(compile_units);
+    let mut generator = Generator::new(output.clone());
+    if cli.checked {
+        generator.enable_checks(
+            input_files
+                .iter()
+                .map(|(namespace, (path, content))| {
+                    (
+                        namespace.to_owned(),
+                        SourceFile::new(&path.to_string_lossy(), content),
+                    )
+                })
+                .collect(),
+        );
+    }
+    generator.set_mc_version(cli.mc_version);
+    if let Some(commands_report) = cli.commands_report {
+        match read_to_string(&commands_report)
+            .map_err(|err| err.to_string())
+            .and_then(|json| CommandTree::parse(&json))
+        {
+            Ok(command_tree) => generator.set_command_tree(command_tree),
+            Err(err) => {
+                stdout().execute(PrintStyledContent("error".red().bold()))?;
+                println!(": {}: {}", commands_report, err);
+                return Ok(());
+            }
+        }
+    }
+    if let Some(block_registry) = cli.block_registry {
+        match read_to_string(&block_registry) {
+            Ok(registry) => generator.set_block_registry(&registry),
+            Err(err) => {
+                stdout().execute(PrintStyledContent("error".red().bold()))?;
+                println!(": {}: {}", block_registry, err);
+                return Ok(());
+            }
+        }
+    }
+    let generate_result = generator.generate(compile_units);
+    for (file_name_no_extension, warning) in generator.warnings() {
+        let (file_path, content) = &input_files[file_name_no_extension];
+        handle_warning(file_path, content, warning)?;
+    }
     match generate_result {
-        Ok(datapack) => {
+        Ok(mut datapack) => {
+            let stats = optimizer::optimize(&mut datapack);
+            if cli.stats {
+                stdout().execute(PrintStyledContent("   Optimized ".green().bold()))?;
+                println!(
+                    "{} -> {} commands ({:.1}% fewer)",
+                    stats.commands_before,
+                    stats.commands_after,
+                    (stats.commands_before - stats.commands_after) as f64 * 100.0
+                        / stats.commands_before.max(1) as f64
+                );
+            }
             datapack.write_to_file()?;
             let path = Path::new(&output);
             let mut ancestors = path.ancestors();
             ancestors.next();
             let ancestor = ancestors.next();
             if ancestor.is_none() {
-                datapack::mcscript_datapack::mcscript_datapack("mcscript".into())
+                datapack::mcscript_datapack::mcscript_datapack("mcscript".into(), cli.mc_version)
                     .write_to_file()?;
             } else {
                 datapack::mcscript_datapack::mcscript_datapack(
@@ -84,6 +185,7 @@ fn main() -> Result<()> {
                         .to_str()
                         .unwrap()
                         .to_owned(),
+                    cli.mc_version,
                 )
                 .write_to_file()?;
             }
@@ -1,5 +1,7 @@
 use std::{
+    fs::read_to_string,
     io::{stdout, Result},
+    path::Path,
     process::Command,
 };
 
@@ -7,6 +9,79 @@ use crossterm::{
     style::{Print, PrintStyledContent, Stylize},
     ExecutableCommand,
 };
+use lalrpop_util::ParseError;
+
+use crate::{
+    backend::{
+        command_tree::CommandTree,
+        datapack::Datapack,
+        error::{SemanticError, Warning},
+        generator::Generator,
+    },
+    frontend::parser::CompileUnitParser,
+};
+
+/// Compiles the source files `(namespace, source)` with `generator`
+/// without running the result. Included files are looked up in `example`.
+fn generate(
+    generator: &mut Generator,
+    sources: &[(&str, &str)],
+) -> std::result::Result<Datapack, (String, SemanticError)> {
+    let compile_units = sources
+        .iter()
+        .map(|(namespace, source)| {
+            let mut compile_unit = CompileUnitParser::new()
+                .parse(source)
+                .unwrap_or_else(|_| panic!("{} has syntax errors", namespace));
+            compile_unit.resolve_include_paths(Path::new("example"));
+            (compile_unit, namespace.to_string())
+        })
+        .collect();
+    generator.generate(compile_units)
+}
+
+fn compile(
+    sources: &[(&str, &str)],
+    pack_name: &str,
+    mc_version: (u32, u32, u32),
+) -> std::result::Result<Datapack, (String, SemanticError)> {
+    let mut generator = Generator::new(pack_name.into());
+    generator.set_mc_version(mc_version);
+    generate(&mut generator, sources)
+}
+
+/// The error of compiling `source`, which must fail, with the source code it
+/// points at.
+fn expect_error(
+    source: &str,
+    result: std::result::Result<Datapack, (String, SemanticError)>,
+) -> (SemanticError, &str) {
+    let Err((_, err)) = result else {
+        panic!("compiled without errors: {}", source);
+    };
+    let span = match &err {
+        SemanticError::InvalidFormatString { begin, end, .. }
+        | SemanticError::InvalidCommand { begin, end, .. }
+        | SemanticError::DivisionByZero { begin, end } => &source[*begin..*end],
+        _ => "",
+    };
+    (err, span)
+}
+
+fn compile_error(source: &str) -> (SemanticError, &str) {
+    expect_error(source, compile(&[("errors", source)], "errors", (1, 21, 0)))
+}
+
+/// The commands of all functions in `datapack`.
+fn commands(datapack: &Datapack) -> Vec<&str> {
+    datapack
+        .namespaces()
+        .iter()
+        .flat_map(|namespace| namespace.mcfunctions())
+        .flat_map(|mcfunction| mcfunction.commands())
+        .map(|command| command.as_str())
+        .collect()
+}
 
 fn test_one(mcfunction: &str, expected_result: &str) -> Result<bool> {
     stdout()
@@ -157,6 +232,9 @@ fn tests() -> Result<()> {
         ("while_1", "3"),
         ("while_2", "54"),
         ("while_3", "23"),
+        ("loop_1", "2000"),
+        ("loop_2", "2299"),
+        ("loop_3", "25"),
         ("break_1", "1225"),
         ("continue_1", "4900"),
         ("glob_var_1", "2"),
@@ -173,6 +251,7 @@ fn tests() -> Result<()> {
         ("unary_op_2", "-1"),
         ("unary_op_3", "0"),
         ("unary_op_4", "3"),
+        ("unary_op_5", "111"),
         ("binary_op_1", "14"),
         ("binary_op_2", "20"),
         ("binary_op_3", "28"),
@@ -181,6 +260,42 @@ fn tests() -> Result<()> {
         ("binary_op_6", "0"),
         ("binary_op_7", "1"),
         ("binary_op_8", "1"),
+        ("binary_op_9", "10611"),
+        ("const_1", "91"),
+        ("const_2", "48"),
+        ("const_3", "188"),
+        ("const_4", "22"),
+        ("const_5", "[0, 0, 0]"),
+        ("execute_1", "142"),
+        ("recursion_1", "11"),
+        ("recursion_2", "30"),
+        ("return_1", "42"),
+        ("tail_call_1", "125250"),
+        ("tail_call_2", "21"),
+        ("tail_call_3", "[3, 2, 1]"),
+        ("task_1", "11"),
+        ("task_2", "50"),
+        ("inline_1", "73"),
+        ("inline_2", "29"),
+        ("inline_3", "[5, 10, 20]"),
+        ("score_1", "2122"),
+        ("selector_1", "\"@e[tag=r2c3,limit=1,sort=nearest]\""),
+        ("selector_2", "101"),
+        ("nbt_1", "[1, 2, 3, 21, 0]"),
+        ("block_1", "110"),
+        ("text_1", "42"),
+        ("text_2", "[1, 2, 6]"),
+        ("format_1", "3231"),
+        ("resource_1", "2"),
+        ("resource_2", "1501"),
+        ("event_1", "2"),
+        ("event_2", "10"),
+        ("guard_1", "15"),
+        ("export_1", "5342"),
+        ("export_2", "310"),
+        ("export_3", "83"),
+        ("reenter_1", "123"),
+        ("reenter_2", "369"),
         ("sort_1", "[99, 153, 243, 274, 389, 397, 467, 503, 561, 597, 608, 637, 658, 687, 717, 720, 749, 846, 992, 994]"),
         ("sort_2", "[99, 153, 243, 274, 389, 397, 467, 503, 561, 597, 608, 637, 658, 687, 717, 720, 749, 846, 992, 994]"),
         ("sort_3", "[99, 153, 243, 274, 389, 397, 467, 503, 561, 597, 608, 637, 658, 687, 717, 720, 749, 846, 992, 994]"),
@@ -195,3 +310,194 @@ fn tests() -> Result<()> {
     assert!(all_pass);
     Ok(())
 }
+
+#[test]
+fn mc_version_1_20_2() -> Result<()> {
+    let dir = std::env::temp_dir().join("mcscript_1_20_2");
+    let pack = dir.to_str().unwrap();
+    let tests = read_to_string("example/tests.mcs")?;
+    let test_utils = read_to_string("example/test_utils.mcs")?;
+    compile(
+        &[("tests", &tests), ("test_utils", &test_utils)],
+        pack,
+        (1, 20, 2),
+    )
+    .unwrap_or_else(|(namespace, err)| panic!("{}: {:?}", namespace, err))
+    .write_to_file()?;
+
+    assert!(read_to_string(dir.join("pack.mcmeta"))?.contains("\"pack_format\": 18"));
+    assert!(dir.join("data/tests/functions/sort_1.mcfunction").is_file());
+    assert!(dir.join("data/tests/tags/functions/hooks.json").is_file());
+    assert!(dir
+        .join("data/minecraft/tags/functions/tick.json")
+        .is_file());
+    assert!(!dir.join("data/tests/function").exists());
+    // without `return`, functions return `int` values in command storage
+    let return_1 = read_to_string(dir.join("data/tests/functions/return_1-label_0.mcfunction"))?;
+    assert!(return_1.contains("memory:temp return_value"));
+    Ok(())
+}
+
+#[test]
+fn format_string_errors() {
+    let (err, span) = compile_error(r#"fn f() { run_command!("say {} {1}", 1); }"#);
+    assert!(
+        matches!(err, SemanticError::InvalidFormatString { message, .. }
+        if message == "the format string needs 2 argument(s), found 1")
+    );
+    assert_eq!(span, r#"run_command!("say {} {1}", 1)"#);
+
+    let (err, span) = compile_error(r#"fn f() { run_command!("say {0} {0}", 1, 2 + 3); }"#);
+    assert!(
+        matches!(err, SemanticError::InvalidFormatString { message, .. }
+        if message == "argument never used in the format string")
+    );
+    assert_eq!(span, "2 + 3");
+
+    let (err, _) = compile_error(r#"fn f() { run_command!("sya {}", 1); }"#);
+    assert!(
+        matches!(err, SemanticError::InvalidFormatString { message, .. }
+        if message == "unknown command \"sya\"")
+    );
+
+    let (err, _) = compile_error(r#"fn f() { run_command!("locatebiome minecraft:plains"); }"#);
+    assert!(
+        matches!(err, SemanticError::InvalidFormatString { message, .. }
+        if message == "command \"locatebiome\" does not exist in Minecraft 1.21.0")
+    );
+
+    let (err, _) = compile_error(r#"fn f() { run_command!("dialog clear @a"); }"#);
+    assert!(
+        matches!(err, SemanticError::InvalidFormatString { message, .. }
+        if message == "command \"dialog\" does not exist in Minecraft 1.21.0")
+    );
+    let source = r#"pub fn f() { run_command!("dialog clear @a"); }"#;
+    assert!(compile(&[("dialog", source)], "dialog", (1, 21, 6)).is_ok());
+
+    // `{name}` is only a placeholder if `name` is a variable in scope
+    let datapack = compile(
+        &[(
+            "format",
+            r#"pub fn f() { let x = 1; run_command!("data merge entity @s {x} {NoAI} {Tags:[]}"); }"#,
+        )],
+        "format",
+        (1, 21, 0),
+    );
+    let datapack = datapack.unwrap_or_else(|(namespace, err)| panic!("{}: {:?}", namespace, err));
+    assert!(commands(&datapack).contains(&"$data merge entity @s $(0) {NoAI} {Tags:[]}"));
+}
+
+#[test]
+fn constant_errors() {
+    for (source, literal) in [
+        ("fn f() -> int { return 2147483648; }", "2147483648"),
+        ("fn f() -> int { return 0x100000000; }", "0x100000000"),
+        ("fn f() -> int { return 040000000000; }", "040000000000"),
+    ] {
+        let Err(ParseError::User { error }) = CompileUnitParser::new().parse(source) else {
+            panic!("parsed without errors: {}", source);
+        };
+        assert_eq!(error.message, "integer literal out of range");
+        assert_eq!(&source[error.begin..error.end], literal);
+    }
+    assert!(CompileUnitParser::new()
+        .parse("fn f() -> int { return 2147483647 + 0x7fffffff; }")
+        .is_ok());
+    // `-` and a literal are one literal, whose range is checked after negation
+    assert!(CompileUnitParser::new()
+        .parse("fn f() -> int { return -2147483648 + -0x80000000 + -020000000000; }")
+        .is_ok());
+    let source = "fn f() -> int { return -2147483649; }";
+    let Err(ParseError::User { error }) = CompileUnitParser::new().parse(source) else {
+        panic!("parsed without errors: {}", source);
+    };
+    assert_eq!(error.message, "integer literal out of range");
+    assert_eq!(&source[error.begin..error.end], "-2147483649");
+
+    let (err, span) = compile_error("fn f(x: int) -> int { return x / (3 - 3); }");
+    assert!(matches!(err, SemanticError::DivisionByZero { .. }));
+    assert_eq!(span, "3 - 3");
+
+    let (err, span) =
+        compile_error("const ZERO: int = 0; fn f(x: int) -> int { return x % ZERO; }");
+    assert!(matches!(err, SemanticError::DivisionByZero { .. }));
+    assert_eq!(span, "ZERO");
+
+    let (err, span) = compile_error("const C: int = 1 / 0;");
+    assert!(matches!(err, SemanticError::DivisionByZero { .. }));
+    assert_eq!(span, "0");
+}
+
+#[test]
+fn unused_functions() {
+    let source = r#"
+        pub fn main() { used(); }
+        fn used() {}
+        fn unused() { used(); }
+    "#;
+    let mut generator = Generator::new("unused".into());
+    generate(&mut generator, &[("unused", source)])
+        .unwrap_or_else(|(namespace, err)| panic!("{}: {:?}", namespace, err));
+    // `used` is left out as well, but its body is inlined into `main`
+    assert!(matches!(generator.warnings(),
+        [(namespace, Warning::UnusedFunction { function, begin, end })]
+        if namespace == "unused" && function == "unused" && &source[*begin..*end] == "unused"));
+}
+
+#[test]
+fn loop_abort_check() {
+    // loops that run out of calls leave the function also without `--checked`
+    let source = "pub fn f() { let i = 0; while i < 10 { i += 1; } }";
+    let datapack = compile(&[("loops", source)], "loops", (1, 21, 0))
+        .unwrap_or_else(|(namespace, err)| panic!("{}: {:?}", namespace, err));
+    let commands = commands(&datapack);
+    let loop_call = commands
+        .iter()
+        .position(|command| command.starts_with("function mcscript:loop "))
+        .unwrap();
+    assert_eq!(
+        commands[loop_call + 1],
+        "execute if score abort registers matches 1 run return 0"
+    );
+}
+
+#[test]
+fn commands_report() -> Result<()> {
+    // a few of the commands of Minecraft, enough for the code below
+    let report = read_to_string("example/resources/commands.json")?;
+    let compile = |source: &str| {
+        let mut generator = Generator::new("report".into());
+        generator.set_command_tree(CommandTree::parse(&report).unwrap());
+        generate(&mut generator, &[("report", source)])
+    };
+
+    let source = r#"pub fn f() {
+        let x = 2;
+        run_command!("kill @e[type=pig]");
+        run_command!("xp add @a {x}");
+        run_command!("execute as @a as @s run say hi");
+        run_mod_command!("mymod:spawn_boss {x}");
+    }"#;
+    let datapack =
+        compile(source).unwrap_or_else(|(namespace, err)| panic!("{}: {:?}", namespace, err));
+    assert!(commands(&datapack).contains(&"execute as @a as @s run say hi"));
+    // commands of mods are not in the report and not checked
+    assert!(commands(&datapack).contains(&"$mymod:spawn_boss $(0)"));
+
+    let source = r#"pub fn f() { run_command!("kill @e pig"); }"#;
+    let (err, span) = expect_error(source, compile(source));
+    assert!(matches!(err, SemanticError::InvalidCommand { message, .. }
+        if message == "unknown or incomplete command: \"kill @e<--[HERE]\""));
+    assert_eq!(span, r#"run_command!("kill @e pig")"#);
+
+    let source = r#"pub fn f() { run_command!("xp add @a lots"); }"#;
+    let (err, _) = expect_error(source, compile(source));
+    assert!(matches!(err, SemanticError::InvalidCommand { message, .. }
+        if message == "unknown or incomplete command: \"xp add @a<--[HERE]\""));
+
+    let source = r#"pub fn f() { run_command!("execute as @a run kil @s"); }"#;
+    let (err, _) = expect_error(source, compile(source));
+    assert!(matches!(err, SemanticError::InvalidCommand { message, .. }
+        if message == "unknown or incomplete command: \"execute as @a run<--[HERE]\""));
+    Ok(())
+}